@@ -1,6 +1,6 @@
 use std::path::PathBuf;
 
-use clap::{ArgAction, Parser};
+use clap::{ArgAction, Parser, ValueEnum};
 
 #[derive(Parser, Debug)]
 #[clap(
@@ -23,6 +23,15 @@ pub struct Arguments {
     #[clap(short, long)]
     pub name: Option<String>,
 
+    /// Override a template variable, e.g. `--var machine.threads=16`.
+    /// Values are parsed as yaml scalars, so `true` and `16` become a boolean and a number.
+    #[clap(long = "var", value_name = "KEY=VALUE", global = true)]
+    pub vars: Vec<String>,
+
+    /// Override template variables with the variables of a yaml file.
+    #[clap(long = "vars-file", value_name = "PATH", global = true)]
+    pub vars_files: Vec<PathBuf>,
+
     #[command(subcommand)]
     pub subcommand: Subcommand,
 }
@@ -30,12 +39,34 @@ pub struct Arguments {
 #[derive(Parser, Debug)]
 pub enum Subcommand {
     /// Run a dry-run on the current system and see all changes that would executed.
-    Plan,
+    Plan {
+        /// Wait for other running bois processes to finish instead of failing.
+        #[clap(long)]
+        wait: bool,
+    },
     /// Actually deploy all changes to the system
-    Deploy,
+    Deploy {
+        /// Wait for other running bois processes to finish instead of failing.
+        #[clap(long)]
+        wait: bool,
+    },
     /// Show the diff between the current system and the target.
     /// This only shows differences in system services and packages.
     Diff,
+    /// Show files on the system that aren't managed by bois, but live in directories that are.
+    Status,
+    /// Show all variables that're used by templates and where they come from.
+    /// Fails if any template uses undefined variables.
+    Lint,
+    /// Show the merged variables of this host and where each of them comes from.
+    Vars {
+        /// Show the variables that're used for the files of this group instead.
+        #[clap(short, long)]
+        group: Option<String>,
+        /// The format of the output.
+        #[clap(short, long, value_enum, default_value_t = VarsFormat::Yaml)]
+        format: VarsFormat,
+    },
     /// Check the system for any changes since the last deployment.
     /// If any are found, try to integrate them back into the configuration.
     Absorb,
@@ -46,3 +77,10 @@ pub enum Subcommand {
         directory: Option<PathBuf>,
     },
 }
+
+/// The output format of `bois vars`.
+#[derive(Clone, Copy, Debug, ValueEnum)]
+pub enum VarsFormat {
+    Yaml,
+    Json,
+}
@@ -1,6 +1,7 @@
 use std::collections::{HashMap, HashSet};
 
 use crate::{
+    deployed_state::DeployedState,
     handlers::{packages::PackageManager, services::ServiceManager},
     state::{State, group::Group, host::Host},
 };
@@ -38,6 +39,25 @@ impl CompiledState {
 
         compiled_state
     }
+
+    /// Build the compiled state of a previous deployment from its manifest.
+    pub fn from_deployed_state(state: &DeployedState) -> Self {
+        let mut compiled_state = Self::default();
+
+        for (manager, packages) in state.packages.iter() {
+            compiled_state
+                .deployed_packages
+                .insert(*manager, packages.iter().cloned().collect());
+        }
+
+        for (manager, services) in state.services.iter() {
+            compiled_state
+                .enabled_services
+                .insert(*manager, services.iter().cloned().collect());
+        }
+
+        compiled_state
+    }
 }
 
 fn handle_host(_compiled_state: &mut CompiledState, _host: &Host) {}
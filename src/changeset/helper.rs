@@ -1,3 +1,12 @@
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Result;
+
+use crate::error::Error;
+
 /// Check whether two unix modes are identical.
 pub fn equal_mode(one: u32, two: u32) -> bool {
     // Remove the filetype mode bits, as we're not interested in them
@@ -21,3 +30,24 @@ pub fn remove_filetype(mut number: u32) -> u32 {
 
     number
 }
+
+/// Get all entries of a directory on the system that aren't managed by bois.
+///
+/// The returned paths are sorted, so output and operations are deterministic.
+pub fn unmanaged_entries(directory: &Path, managed: &HashSet<PathBuf>) -> Result<Vec<PathBuf>> {
+    let entries = std::fs::read_dir(directory)
+        .map_err(|err| Error::IoPath(directory.to_path_buf(), "reading directory", err))?;
+
+    let mut unmanaged = Vec::new();
+    for entry in entries {
+        let entry =
+            entry.map_err(|err| Error::IoPath(directory.to_path_buf(), "reading entry", err))?;
+        let path = entry.path();
+        if !managed.contains(&path) {
+            unmanaged.push(path);
+        }
+    }
+    unmanaged.sort();
+
+    Ok(unmanaged)
+}
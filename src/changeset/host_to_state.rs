@@ -3,9 +3,10 @@
 //! last deploy.
 //! We can then inform the user about these changes, so they aren't unintentionally overwritten.
 use std::{
-    fs::read_to_string,
+    collections::HashMap,
+    fs::{read, read_to_string},
     os::unix::fs::{MetadataExt, PermissionsExt},
-    path::PathBuf,
+    path::{Path, PathBuf},
 };
 
 use anyhow::{Context, Result};
@@ -18,33 +19,31 @@ use super::{
     PackageUninstall,
     PathOperation,
     helper::{equal_mode, remove_filetype},
+    managed_block::BlockMarkers,
 };
 use crate::{
     config::bois::Configuration,
+    deployed_state::{DeployedDirectory, DeployedFile, DeployedPath, DeployedState, hash_content},
     error::Error,
-    state::{State, file::Entry, group::Group, host::Host},
+    state::{
+        State,
+        directory::Directory,
+        file::{Entry, File},
+    },
     system_state::SystemState,
 };
 
 pub fn create_changeset(
     config: &Configuration,
     system_state: &mut SystemState,
-    old_state: &State,
+    old_state: &DeployedState,
     new_state: &State,
 ) -> Result<Changeset> {
     // Create changeset for packages that should be cleaned up.
     let package_uninstalls = handle_packages(system_state, old_state, new_state)?;
 
-    let mut path_operations = Vec::new();
-    // Create changeset for files and system services on host config.
-    let host_changeset = handle_host(config, &old_state.host, system_state)?;
-    path_operations.extend(host_changeset);
-
-    // Create changeset for files and system services on group configs.
-    for group in old_state.host.groups.iter() {
-        let group_changset = handle_group(config, group, system_state)?;
-        path_operations.extend(group_changset);
-    }
+    // Create changeset for all deployed files and directories.
+    let path_operations = handle_paths(config, old_state, new_state)?;
 
     Ok(Changeset {
         package_uninstalls,
@@ -60,7 +59,7 @@ pub fn create_changeset(
 /// We don't want to show removed packages that aren't desired.
 fn handle_packages(
     system_state: &mut SystemState,
-    old_state: &State,
+    old_state: &DeployedState,
     new_state: &State,
 ) -> Result<Vec<PackageUninstall>> {
     let mut changeset = Vec::new();
@@ -93,39 +92,44 @@ fn handle_packages(
     Ok(changeset)
 }
 
-/// If anything happened on the deployed files of the host since the last deploy, create a
-/// changeset that reflects those changes.
+/// If anything happened on the deployed paths since the last deploy, create a changeset that
+/// reflects those changes.
 ///
 /// It's effectively the inverse logic to the `state_to_host` logic.
-fn handle_host(
+fn handle_paths(
     config: &Configuration,
-    host: &Host,
-    _system_state: &mut SystemState,
+    old_state: &DeployedState,
+    new_state: &State,
 ) -> Result<Vec<PathOperation>> {
     let mut changeset = Vec::new();
 
-    for entry in host.directory.entries.iter() {
-        handle_entry(&config.target_dir, entry, &mut changeset)?;
+    // The manifest only contains hashes of the deployed content.
+    // To still be able to show a diff for modified files, we look up the content in the desired
+    // state. As long as the source hasn't changed since the last deployment, it's identical to
+    // the deployed content.
+    let mut desired_files = HashMap::new();
+    collect_files(
+        &config.target_dir,
+        &new_state.host.directory,
+        &mut desired_files,
+    );
+    for group in new_state.host.groups.iter() {
+        collect_files(&config.target_dir, &group.directory, &mut desired_files);
     }
 
-    // Return the reversed changeset.
-    // Changes should be executed in the reverse order, as we're scanning files from the top to the
-    // bottom of the file tree. But we need to remove files from the bottom to the top.
-    changeset.reverse();
-    Ok(changeset)
-}
-
-/// If anything happened on the deployed files of this group since the last deploy, create a
-/// changeset that reflects those changes.
-fn handle_group(
-    config: &Configuration,
-    group: &Group,
-    _system_state: &mut SystemState,
-) -> Result<Vec<PathOperation>> {
-    let mut changeset = Vec::new();
+    for deployed_path in old_state.paths.iter() {
+        match deployed_path {
+            DeployedPath::File(file) => handle_file(file, &desired_files, &mut changeset)?,
+            DeployedPath::Directory(dir) => handle_directory(dir, &mut changeset)?,
+        }
+    }
 
-    for entry in group.directory.entries.iter() {
-        handle_entry(&config.target_dir, entry, &mut changeset)?;
+    // Paths that must be absent but showed up again since the last deployment.
+    // Only report those that are still supposed to be absent.
+    for path in old_state.absent.iter() {
+        if new_state.absent_paths.contains(path) {
+            handle_absent(path, &mut changeset)?;
+        }
     }
 
     // Return the reversed changeset.
@@ -135,148 +139,228 @@ fn handle_group(
     Ok(changeset)
 }
 
-fn handle_entry(root: &PathBuf, entry: &Entry, changeset: &mut Vec<PathOperation>) -> Result<()> {
-    match entry {
-        Entry::File(file) => {
-            let path = file.file_path(root);
+/// Recursively collect all files of a directory by their target path and managed block.
+/// Several files may manage different blocks inside the same target file.
+fn collect_files<'a>(root: &Path, directory: &'a Directory, files: &mut DesiredFiles<'a>) {
+    for entry in directory.entries.iter() {
+        match entry {
+            Entry::File(file) => {
+                files.insert((file.file_path(root), file.block_markers()), file);
+            }
+            Entry::Directory(dir) => collect_files(root, dir, files),
+        }
+    }
+}
 
-            // Check whether the target file exists.
-            // If it doesn't, it has been deleted in the meantime.
-            if !path.exists() {
-                let change = FileOperation::Delete { path };
-                changeset.push(PathOperation::File(change));
+/// The files of the desired state by their target path and managed block.
+type DesiredFiles<'a> = HashMap<(PathBuf, Option<BlockMarkers>), &'a File>;
 
-                return Ok(());
-            }
+fn handle_file(
+    file: &DeployedFile,
+    desired_files: &DesiredFiles,
+    changeset: &mut Vec<PathOperation>,
+) -> Result<()> {
+    let path = file.path.clone();
 
-            // At this point we know that the file already exists.
-            // We now have to check for any changes and whether we have to modify the file.
-            let mut modified_content = None;
-            let mut modified_mode = None;
-            let mut modified_owner = None;
-            let mut modified_group = None;
-
-            // Check whether content matches
-            // TODO: This is the wrong way around for now.
-            //   Or rather, we need to write a custom display logic for this case.
-            //   In the normal case, the new file is compared with the old one on disk.
-            //   In this case however, we must compare the old one from the state with the new on
-            //   from the disk.
-            //   Right now, we just set the file content to that of the old state, but this results
-            //   in the diff being generated the wrong way around.
-            let content = read_to_string(&path)
-                .map_err(|err| Error::IoPath(path.clone(), "reading file", err))?;
-            if content.trim() != file.content.trim() {
-                modified_content = Some(file.content.clone());
-            }
+    // Check whether the target file exists.
+    // If it doesn't, it has been deleted in the meantime.
+    if !path.exists() {
+        let change = FileOperation::Delete { path };
+        changeset.push(PathOperation::File(change));
 
-            let metadata = path
-                .metadata()
-                .map_err(|err| Error::IoPath(path.clone(), "reading metadata", err))?;
+        return Ok(());
+    }
 
-            // Check whether permissions patch
-            let file_mode = metadata.permissions().mode();
-            if !equal_mode(file_mode, file.mode()) {
-                modified_mode = Some(remove_filetype(file_mode));
-            }
+    // At this point we know that the file already exists.
+    // We now have to check for any changes and whether we have to modify the file.
+    let mut content_changed = false;
+    let mut modified_content = None;
+    let mut modified_mode = None;
+    let mut modified_owner = None;
+    let mut modified_group = None;
+
+    // Check whether content matches
+    // TODO: This is the wrong way around for now.
+    //   Or rather, we need to write a custom display logic for this case.
+    //   In the normal case, the new file is compared with the old one on disk.
+    //   In this case however, we must compare the old one from the state with the new on
+    //   from the disk.
+    //   Right now, we just set the file content to that of the deployed state, but this results
+    //   in the diff being generated the wrong way around.
+    // The content of create-only files belongs to the system, so it can't drift.
+    if !file.create_only {
+        let content = read_to_string(&path)
+            .map_err(|err| Error::IoPath(path.clone(), "reading file", err))?;
+
+        // If only a block is managed, changes outside of it aren't drift.
+        // A missing block always counts as a change.
+        let managed_content = match &file.block {
+            Some(markers) => markers.extract(&content),
+            None => Some(content.clone()),
+        };
+
+        if managed_content.is_none_or(|managed| hash_content(&managed) != file.hash) {
+            content_changed = true;
+            // Only show a diff if we still know the deployed content.
+            modified_content = desired_files
+                .get(&(path.clone(), file.block.clone()))
+                .filter(|desired| hash_content(&desired.content) == file.hash)
+                .map(|desired| match &file.block {
+                    Some(markers) => markers.replace(&content, &desired.content),
+                    None => desired.content.clone(),
+                });
+        }
+    }
 
-            // Compare owner
-            let uid = metadata.uid();
-            let user = NixUser::from_uid(Uid::from_raw(uid))?.context(format!(
-                "Couldn't get username for uid {uid} on file {path:?}"
-            ))?;
-            if user.name != file.config.owner() {
-                modified_owner = Some(user.name)
-            }
+    let metadata = path
+        .metadata()
+        .map_err(|err| Error::IoPath(path.clone(), "reading metadata", err))?;
 
-            // Compare group
-            let gid = metadata.gid();
-            let group = NixGroup::from_gid(Gid::from_raw(gid))?
-                .context(format!("Couldn't get groupname for gid {gid}"))?;
-            if group.name != file.config.group() {
-                modified_group = Some(group.name)
-            }
+    // Check whether permissions patch
+    // The metadata of block files is only recorded if it's set explicitly.
+    let file_mode = metadata.permissions().mode();
+    if file.mode.is_some_and(|mode| !equal_mode(file_mode, mode)) {
+        modified_mode = Some(remove_filetype(file_mode));
+    }
 
-            // If anything has been modified, push a change.
-            if modified_content.is_some()
-                || modified_owner.is_some()
-                || modified_group.is_some()
-                || modified_mode.is_some()
-            {
-                let change = FileOperation::Modify {
-                    path,
-                    content: modified_content.map(|str| str.into_bytes()),
-                    mode: modified_mode,
-                    owner: modified_owner,
-                    group: modified_group,
-                };
-                changeset.push(PathOperation::File(change));
-            }
+    // Compare owner
+    if let Some(owner) = &file.owner {
+        let uid = metadata.uid();
+        let user = NixUser::from_uid(Uid::from_raw(uid))?.context(format!(
+            "Couldn't get username for uid {uid} on file {path:?}"
+        ))?;
+        if &user.name != owner {
+            modified_owner = Some(user.name)
         }
-        Entry::Directory(dir) => {
-            let path = dir.file_path(root);
+    }
 
-            // Check whether the target directory exists.
-            // If it doesn't, it has been deleted in the meantime.
-            if !path.exists() {
-                let change = DirectoryOperation::Delete { path };
+    // Compare group
+    if let Some(group_name) = &file.group {
+        let gid = metadata.gid();
+        let group = NixGroup::from_gid(Gid::from_raw(gid))?
+            .context(format!("Couldn't get groupname for gid {gid}"))?;
+        if &group.name != group_name {
+            modified_group = Some(group.name)
+        }
+    }
 
-                changeset.push(PathOperation::Directory(change));
+    // If anything has been modified, push a change.
+    if content_changed
+        || modified_owner.is_some()
+        || modified_group.is_some()
+        || modified_mode.is_some()
+    {
+        let change = FileOperation::Modify {
+            path,
+            content: modified_content.map(|str| str.into_bytes()),
+            mode: modified_mode,
+            owner: modified_owner,
+            group: modified_group,
+        };
+        changeset.push(PathOperation::File(change));
+    }
 
-                for entry in dir.entries.iter() {
-                    handle_entry(root, entry, changeset)?;
-                }
-                return Ok(());
-            }
+    Ok(())
+}
 
-            // At this point we know that the directory already exists.
-            // We now have to check for any changes and whether we have to modify the directory.
-            let mut modified_mode = None;
-            let mut modified_owner = None;
-            let mut modified_group = None;
+fn handle_directory(dir: &DeployedDirectory, changeset: &mut Vec<PathOperation>) -> Result<()> {
+    let path = dir.path.clone();
 
-            let metadata = path
-                .metadata()
-                .map_err(|err| Error::IoPath(path.clone(), "reading metadata", err))?;
+    // Check whether the target directory exists.
+    // If it doesn't, it has been deleted in the meantime.
+    if !path.exists() {
+        let change = DirectoryOperation::Delete { path };
+        changeset.push(PathOperation::Directory(change));
 
-            // Check whether mode matches
-            let file_mode = metadata.permissions().mode();
-            if !equal_mode(metadata.permissions().mode(), dir.config.mode()) {
-                modified_mode = Some(remove_filetype(file_mode));
-            }
+        return Ok(());
+    }
 
-            // Compare owner
-            let uid = metadata.uid();
-            let user = NixUser::from_uid(Uid::from_raw(uid))?.context(format!(
-                "Couldn't get username for uid {uid} on file {path:?}"
-            ))?;
-            if user.name != dir.config.owner() {
-                modified_owner = Some(user.name)
-            }
+    // At this point we know that the directory already exists.
+    // We now have to check for any changes and whether we have to modify the directory.
+    let mut modified_mode = None;
+    let mut modified_owner = None;
+    let mut modified_group = None;
 
-            // Compare group
-            let gid = metadata.gid();
-            let group = NixGroup::from_gid(Gid::from_raw(gid))?
-                .context(format!("Couldn't get groupname for gid {gid}"))?;
-            if group.name != dir.config.group() {
-                modified_group = Some(group.name)
-            }
+    let metadata = path
+        .metadata()
+        .map_err(|err| Error::IoPath(path.clone(), "reading metadata", err))?;
 
-            // If anything has been modified, push a change.
-            if modified_owner.is_some() || modified_group.is_some() || modified_mode.is_some() {
-                let change = DirectoryOperation::Modify {
-                    path,
-                    mode: modified_mode,
-                    owner: modified_owner,
-                    group: modified_group,
-                };
-                changeset.push(PathOperation::Directory(change));
-            }
+    // Check whether mode matches
+    let file_mode = metadata.permissions().mode();
+    if !equal_mode(file_mode, dir.mode) {
+        modified_mode = Some(remove_filetype(file_mode));
+    }
 
-            for entry in dir.entries.iter() {
-                handle_entry(root, entry, changeset)?;
-            }
-        }
+    // Compare owner
+    let uid = metadata.uid();
+    let user = NixUser::from_uid(Uid::from_raw(uid))?.context(format!(
+        "Couldn't get username for uid {uid} on file {path:?}"
+    ))?;
+    if user.name != dir.owner {
+        modified_owner = Some(user.name)
+    }
+
+    // Compare group
+    let gid = metadata.gid();
+    let group = NixGroup::from_gid(Gid::from_raw(gid))?
+        .context(format!("Couldn't get groupname for gid {gid}"))?;
+    if group.name != dir.group {
+        modified_group = Some(group.name)
+    }
+
+    // If anything has been modified, push a change.
+    if modified_owner.is_some() || modified_group.is_some() || modified_mode.is_some() {
+        let change = DirectoryOperation::Modify {
+            path,
+            mode: modified_mode,
+            owner: modified_owner,
+            group: modified_group,
+        };
+        changeset.push(PathOperation::Directory(change));
+    }
+
+    Ok(())
+}
+
+/// Detect a path that must be absent, but has been created on the system.
+fn handle_absent(path: &Path, changeset: &mut Vec<PathOperation>) -> Result<()> {
+    let Ok(metadata) = path.symlink_metadata() else {
+        return Ok(());
+    };
+
+    // Get the owner and group of the path.
+    let uid = metadata.uid();
+    let user = NixUser::from_uid(Uid::from_raw(uid))?.context(format!(
+        "Couldn't get username for uid {uid} on file {path:?}"
+    ))?;
+    let gid = metadata.gid();
+    let group = NixGroup::from_gid(Gid::from_raw(gid))?
+        .context(format!("Couldn't get groupname for gid {gid}"))?;
+
+    let mode = remove_filetype(metadata.permissions().mode());
+    if metadata.is_dir() {
+        let change = DirectoryOperation::Create {
+            path: path.to_path_buf(),
+            mode,
+            owner: user.name,
+            group: group.name,
+        };
+        changeset.push(PathOperation::Directory(change));
+    } else {
+        // Symlinks and special files have no content we could show.
+        let content = if metadata.is_file() {
+            read(path).map_err(|err| Error::IoPath(path.to_path_buf(), "reading file", err))?
+        } else {
+            Vec::new()
+        };
+        let change = FileOperation::Create {
+            path: path.to_path_buf(),
+            content,
+            mode,
+            owner: user.name,
+            group: group.name,
+        };
+        changeset.push(PathOperation::File(change));
     }
 
     Ok(())
@@ -0,0 +1,81 @@
+//! This module contains logic for managed blocks.
+//!
+//! Files with a managed block are mostly owned by the system or some other tool.
+//! bois only owns a marked region inside of them and leaves everything else untouched:
+//!
+//! ```text
+//! 127.0.0.1 localhost
+//! # BEGIN bois hosts
+//! 192.168.1.10 nas
+//! # END bois hosts
+//! ```
+use serde::{Deserialize, Serialize};
+
+/// The lines that enclose a managed block inside a file.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct BlockMarkers {
+    pub begin: String,
+    pub end: String,
+}
+
+impl BlockMarkers {
+    /// Create the markers for the block with the given `id`.
+    /// `comment` is the comment prefix of the file, e.g. `#` or `//`.
+    pub fn new(id: &str, comment: &str) -> Self {
+        Self {
+            begin: format!("{comment} BEGIN bois {id}"),
+            end: format!("{comment} END bois {id}"),
+        }
+    }
+
+    /// Find the line range of the block, including the marker lines.
+    fn find(&self, lines: &[&str]) -> Option<(usize, usize)> {
+        let begin = lines.iter().position(|line| line.trim() == self.begin)?;
+        let end = lines[begin..]
+            .iter()
+            .position(|line| line.trim() == self.end)?;
+
+        Some((begin, begin + end))
+    }
+
+    /// Get the content of the block, without its markers.
+    ///
+    /// Returns `None` if the file doesn't contain the block.
+    pub fn extract(&self, content: &str) -> Option<String> {
+        let lines: Vec<&str> = content.lines().collect();
+        let (begin, end) = self.find(&lines)?;
+
+        Some(lines[begin + 1..end].join("\n"))
+    }
+
+    /// Set the content of the block inside the given file content.
+    ///
+    /// If the file doesn't contain the block yet, it's appended to the end of the file.
+    /// Everything outside of the block is left untouched.
+    pub fn replace(&self, content: &str, block: &str) -> String {
+        let mut section = vec![self.begin.as_str()];
+        if !block.trim().is_empty() {
+            section.push(block.trim_matches('\n'));
+        }
+        section.push(self.end.as_str());
+
+        let lines: Vec<&str> = content.lines().collect();
+        let mut new_lines = match self.find(&lines) {
+            Some((begin, end)) => {
+                let mut new_lines = lines[..begin].to_vec();
+                new_lines.extend(section);
+                new_lines.extend(&lines[end + 1..]);
+                new_lines
+            }
+            None => {
+                let mut new_lines = lines;
+                new_lines.extend(section);
+                new_lines
+            }
+        };
+
+        // Files should always end with a newline.
+        new_lines.push("");
+        new_lines.join("\n")
+    }
+}
@@ -5,6 +5,8 @@ use crate::handlers::packages::PackageManager;
 pub mod compiled_state;
 pub mod helper;
 pub mod host_to_state;
+pub mod managed_block;
+pub mod settings;
 pub mod state_to_host;
 pub mod state_to_state;
 pub mod tree;
@@ -18,6 +20,10 @@ pub struct Changeset {
     pub package_installs: Vec<PackageInstall>,
     pub package_uninstalls: Vec<PackageUninstall>,
     pub path_operations: Vec<PathOperation>,
+    /// Commands that run after all path operations, as some of their paths have changed.
+    pub change_hooks: Vec<ChangeHook>,
+    /// Validations of new file contents, which must pass before anything is executed.
+    pub validations: Vec<FileValidation>,
 }
 
 impl Changeset {
@@ -29,6 +35,7 @@ impl Changeset {
         self.package_installs.is_empty()
             && self.package_uninstalls.is_empty()
             && self.path_operations.is_empty()
+            && self.change_hooks.is_empty()
     }
 
     /// Merge changes of the given changeset into self.
@@ -38,6 +45,33 @@ impl Changeset {
         self.package_installs.extend(other.package_installs);
         self.package_uninstalls.extend(other.package_uninstalls);
         self.path_operations.extend(other.path_operations);
+        self.validations.extend(other.validations);
+        for hook in other.change_hooks {
+            for path in hook.paths {
+                self.add_change_hook(&hook.command, path);
+            }
+        }
+    }
+
+    /// Queue a change hook that has been triggered by a change to the given path.
+    ///
+    /// Hooks are deduplicated by their command, so each command only runs once per deploy.
+    pub fn add_change_hook(&mut self, command: &str, path: PathBuf) {
+        match self
+            .change_hooks
+            .iter_mut()
+            .find(|hook| hook.command == command)
+        {
+            Some(hook) => {
+                if !hook.paths.contains(&path) {
+                    hook.paths.push(path);
+                }
+            }
+            None => self.change_hooks.push(ChangeHook {
+                command: command.to_string(),
+                paths: vec![path],
+            }),
+        }
     }
 }
 
@@ -53,6 +87,24 @@ pub struct PackageInstall {
     pub name: String,
 }
 
+/// A command that's run after all path operations have been executed, as at least one of the
+/// paths it watches has been created or modified.
+#[derive(Debug)]
+pub struct ChangeHook {
+    pub command: String,
+    /// The paths whose changes triggered this hook.
+    pub paths: Vec<PathBuf>,
+}
+
+/// A command that checks the new content of a file before it's written to its path.
+#[derive(Debug)]
+pub struct FileValidation {
+    /// The path the content is going to be deployed to.
+    pub path: PathBuf,
+    pub command: String,
+    pub content: Vec<u8>,
+}
+
 //#[derive(Debug)]
 //pub enum ServiceOperation {
 //    Enable {
@@ -0,0 +1,214 @@
+//! This module contains logic for structured key/value settings.
+//!
+//! Instead of managing a whole file, only some keys inside of an existing key/value file are
+//! asserted. This works for all line based `key=value` formats, such as `sysctl.conf`,
+//! `/etc/environment`, INI files and systemd unit files.
+//!
+//! Comments, ordering and the formatting of all other lines are kept as they are.
+use anyhow::{Result, bail};
+use serde::{Deserialize, Serialize};
+use serde_yaml::{Mapping, Value};
+
+/// The keys of a single section of a key/value file.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SettingsSection {
+    /// The name of the section, e.g. `Login` for `[Login]`.
+    /// `None` for keys that aren't inside of any section.
+    pub name: Option<String>,
+    /// The keys and their desired values.
+    /// Keys with a value of `None` are removed from the file.
+    pub values: Vec<(String, Option<String>)>,
+}
+
+/// All settings that should be asserted in a single file.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Settings {
+    pub sections: Vec<SettingsSection>,
+}
+
+impl Settings {
+    /// Build the settings from their yaml representation.
+    ///
+    /// Nested mappings are sections, all other values are keys outside of any section.
+    /// ```yaml
+    /// vm.swappiness: 10
+    /// Login:
+    ///   HandleLidSwitch: ignore
+    ///   HandlePowerKey: null
+    /// ```
+    pub fn from_yaml(mapping: &Mapping) -> Result<Self> {
+        let mut global = SettingsSection {
+            name: None,
+            values: Vec::new(),
+        };
+        let mut sections = Vec::new();
+
+        for (key, value) in mapping {
+            let key = scalar(key)?.unwrap_or_default();
+            match value {
+                Value::Mapping(section) => {
+                    let mut values = Vec::new();
+                    for (inner_key, inner_value) in section {
+                        values.push((scalar(inner_key)?.unwrap_or_default(), scalar(inner_value)?));
+                    }
+                    sections.push(SettingsSection {
+                        name: Some(key),
+                        values,
+                    });
+                }
+                value => global.values.push((key, scalar(value)?)),
+            }
+        }
+
+        if !global.values.is_empty() {
+            sections.insert(0, global);
+        }
+
+        Ok(Settings { sections })
+    }
+
+    /// Set or remove all keys in the given file content.
+    ///
+    /// Existing keys are changed in place, while their separator (e.g. `=` or ` = `) is kept.
+    /// Missing keys are added to the end of their section. Missing sections are appended to the
+    /// end of the file.
+    pub fn apply(&self, content: &str) -> String {
+        let mut lines: Vec<String> = content.lines().map(ToOwned::to_owned).collect();
+
+        // New keys use the same separator as the existing keys of the file.
+        let separator = lines
+            .iter()
+            .find_map(|line| parse_assignment(line).map(|(_, separator, _)| separator))
+            .unwrap_or_else(|| "=".to_string());
+
+        for section in self.sections.iter() {
+            for (key, value) in section.values.iter() {
+                set_value(&mut lines, section.name.as_deref(), key, value, &separator);
+            }
+        }
+
+        // Files should always end with a newline.
+        lines.push(String::new());
+        lines.join("\n")
+    }
+}
+
+/// Convert a yaml scalar to the string that's written to the file.
+fn scalar(value: &Value) -> Result<Option<String>> {
+    let value = match value {
+        Value::Null => return Ok(None),
+        Value::Bool(value) => value.to_string(),
+        Value::Number(value) => value.to_string(),
+        Value::String(value) => value.clone(),
+        _ => bail!("Settings may only contain sections and scalar values, found: {value:?}"),
+    };
+
+    Ok(Some(value))
+}
+
+/// Get the name of a section header line, e.g. `Login` for `[Login]`.
+fn parse_section(line: &str) -> Option<&str> {
+    line.trim()
+        .strip_prefix('[')
+        .and_then(|line| line.strip_suffix(']'))
+        .map(str::trim)
+}
+
+/// Split a `key = value` line into its key, separator and value.
+/// Comments, empty lines and section headers aren't assignments.
+fn parse_assignment(line: &str) -> Option<(String, String, String)> {
+    let trimmed = line.trim_start();
+    if trimmed.is_empty() || trimmed.starts_with(['#', ';', '[']) {
+        return None;
+    }
+
+    let (key, value) = trimmed.split_once('=')?;
+    let separator = format!(
+        "{}={}",
+        &key[key.trim_end().len()..],
+        &value[..value.len() - value.trim_start().len()]
+    );
+
+    Some((key.trim().to_string(), separator, value.trim().to_string()))
+}
+
+/// Set or remove a single key inside of a section.
+fn set_value(
+    lines: &mut Vec<String>,
+    section: Option<&str>,
+    key: &str,
+    value: &Option<String>,
+    separator: &str,
+) {
+    // The index of the last non-empty line inside of the requested section.
+    // For keys without a section, that's the line before the first section header.
+    let mut section_end = None;
+    let mut current_section = None;
+    let mut found = false;
+
+    let mut index = 0;
+    while index < lines.len() {
+        let line = &lines[index];
+        if let Some(name) = parse_section(line) {
+            current_section = Some(name.to_string());
+            if section == Some(name) {
+                section_end = Some(index);
+            }
+            index += 1;
+            continue;
+        }
+
+        if current_section.as_deref() != section {
+            index += 1;
+            continue;
+        }
+
+        if !line.trim().is_empty() {
+            section_end = Some(index);
+        }
+
+        if let Some((line_key, line_separator, _)) = parse_assignment(line) {
+            if line_key == key {
+                found = true;
+                match value {
+                    Some(value) => lines[index] = format!("{key}{line_separator}{value}"),
+                    None => {
+                        lines.remove(index);
+                        continue;
+                    }
+                }
+            }
+        }
+
+        index += 1;
+    }
+
+    // Removed keys and keys that already exist are done.
+    let Some(value) = value else {
+        return;
+    };
+    if found {
+        return;
+    }
+
+    let new_line = format!("{key}{separator}{value}");
+    match (section, section_end) {
+        (_, Some(end)) => lines.insert(end + 1, new_line),
+        // Keys without a section go to the top, before any section header.
+        (None, None) => {
+            let first_section = lines
+                .iter()
+                .position(|line| parse_section(line).is_some())
+                .unwrap_or(lines.len());
+            lines.insert(first_section, new_line);
+        }
+        // The section doesn't exist yet.
+        (Some(section), None) => {
+            if lines.last().is_some_and(|line| !line.trim().is_empty()) {
+                lines.push(String::new());
+            }
+            lines.push(format!("[{section}]"));
+            lines.push(new_line);
+        }
+    }
+}
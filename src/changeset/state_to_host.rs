@@ -1,7 +1,8 @@
 use std::{
+    collections::{BTreeMap, HashSet},
     fs::read_to_string,
     os::unix::fs::{MetadataExt, PermissionsExt},
-    path::PathBuf,
+    path::{Path, PathBuf},
 };
 
 use anyhow::{Context, Result};
@@ -11,14 +12,22 @@ use super::{
     Changeset,
     DirectoryOperation,
     FileOperation,
+    FileValidation,
     PackageInstall,
     PathOperation,
-    helper::equal_mode,
+    helper::{equal_mode, unmanaged_entries},
+    settings::Settings,
 };
 use crate::{
-    config::bois::Configuration,
+    config::{bois::Configuration, file::FileState},
+    constants::{CURRENT_GROUP, CURRENT_USER},
     error::Error,
-    state::{State, file::Entry, group::Group, host::Host},
+    state::{
+        State,
+        file::{Entry, File},
+        group::Group,
+        host::Host,
+    },
     system_state::SystemState,
 };
 
@@ -30,22 +39,73 @@ pub fn create_changeset(
     // Create changeset for missing packages.
     let package_installs = handle_packages(state, system_state)?;
 
-    let mut path_operations = Vec::new();
+    let mut changeset = Changeset {
+        package_installs,
+        ..Default::default()
+    };
+
+    // Files that only manage a block are collected by their target path, as several of them may
+    // manage different blocks inside the same file.
+    let mut blocks = BTreeMap::new();
+
     // Create changeset for files and system services on host config.
-    let host_changeset = handle_host(config, &state.host, system_state)?;
-    path_operations.extend(host_changeset);
+    handle_host(
+        config,
+        &state.host,
+        system_state,
+        &mut blocks,
+        &mut changeset,
+    )?;
 
     // Create changeset for files and system services on group configs.
     for group in state.host.groups.iter() {
-        let group_changset = handle_group(config, group, system_state)?;
-        path_operations.extend(group_changset);
+        handle_group(config, group, system_state, &mut blocks, &mut changeset)?;
     }
 
-    Ok(Changeset {
-        package_installs,
-        path_operations,
-        ..Default::default()
-    })
+    // Apply all blocks of a file in one go.
+    for (path, entries) in blocks.iter() {
+        handle_blocks(path, entries, &mut changeset)?;
+    }
+
+    // Set or remove the requested keys in key/value files.
+    for (path, settings) in state.settings.iter() {
+        handle_settings(path, settings, &mut changeset)?;
+    }
+
+    // Remove all paths that must not exist, in case they showed up on the system.
+    // Paths inside of other absent directories are removed together with them.
+    for path in state.absent_paths.iter() {
+        let covered = state
+            .absent_paths
+            .iter()
+            .any(|other| other != path && path.starts_with(other));
+        if !covered && path.symlink_metadata().is_ok() {
+            queue_removal(path, &mut changeset)?;
+        }
+    }
+
+    // Remove all unmanaged files from directories that're purged.
+    // This must be done after all other entries have been handled, as several groups may deploy
+    // files into the same directory.
+    // The same directory may be declared by several groups, but it's only purged once.
+    let managed_paths = state.managed_paths();
+    let mut purged_paths = HashSet::new();
+    let directories = std::iter::once(&state.host.directory)
+        .chain(state.host.groups.iter().map(|group| &group.directory));
+    for directory in directories {
+        for entry in directory.entries.iter() {
+            handle_purge(
+                &config.target_dir,
+                entry,
+                false,
+                &managed_paths,
+                &mut purged_paths,
+                &mut changeset,
+            )?;
+        }
+    }
+
+    Ok(changeset)
 }
 
 /// Detect any packages that're missing on the current config and queue them for installation.
@@ -74,53 +134,86 @@ fn handle_packages(state: &State, system_state: &mut SystemState) -> Result<Vec<
 
 /// Create the changeset that's needed to reach the desired state of the [HostConfig] from the
 /// current system's state.
-fn handle_host(
+fn handle_host<'a>(
     config: &Configuration,
-    host: &Host,
+    host: &'a Host,
     _system_state: &mut SystemState,
-) -> Result<Vec<PathOperation>> {
-    let mut changeset = Vec::new();
-
+    blocks: &mut Blocks<'a>,
+    changeset: &mut Changeset,
+) -> Result<()> {
     for entry in host.directory.entries.iter() {
-        handle_entry(&config.target_dir, entry, &mut changeset)?;
+        handle_entry(&config.target_dir, entry, &[], blocks, changeset)?;
     }
 
-    Ok(changeset)
+    Ok(())
 }
 
 /// Create the changeset that's needed to reach the desired state of a given [GroupConfig] from the
 /// current system's state.
-fn handle_group(
+fn handle_group<'a>(
     config: &Configuration,
-    group: &Group,
+    group: &'a Group,
     _system_state: &mut SystemState,
-) -> Result<Vec<PathOperation>> {
-    let mut changeset = Vec::new();
-
+    blocks: &mut Blocks<'a>,
+    changeset: &mut Changeset,
+) -> Result<()> {
     for entry in group.directory.entries.iter() {
-        handle_entry(&config.target_dir, entry, &mut changeset)?;
+        handle_entry(&config.target_dir, entry, &[], blocks, changeset)?;
     }
 
-    Ok(changeset)
+    Ok(())
 }
 
-fn handle_entry(root: &PathBuf, entry: &Entry, changeset: &mut Vec<PathOperation>) -> Result<()> {
+/// Files that manage a block, grouped by their target path.
+/// Each file is stored together with the `on_change` hooks of its parent directories.
+type Blocks<'a> = BTreeMap<PathBuf, Vec<(&'a File, Vec<String>)>>;
+
+/// Create all operations that're needed to reach the desired state of a single entry.
+///
+/// `parent_hooks` are the `on_change` hooks of all parent directories, which are triggered by
+/// any change inside of them.
+/// Files that only manage a block are added to `blocks` and handled later on.
+fn handle_entry<'a>(
+    root: &PathBuf,
+    entry: &'a Entry,
+    parent_hooks: &[String],
+    blocks: &mut Blocks<'a>,
+    changeset: &mut Changeset,
+) -> Result<()> {
     match entry {
         Entry::File(file) => {
             let path = file.file_path(root);
 
+            // Absent files are removed together with all other absent paths.
+            // Just like for the `absent` list, no hooks are run for removed files.
+            if file.config.state == FileState::Absent {
+                return Ok(());
+            }
+
+            // Blocks are applied together with all other blocks of the same file.
+            if file.block_markers().is_some() {
+                blocks
+                    .entry(path)
+                    .or_default()
+                    .push((file, parent_hooks.to_vec()));
+                return Ok(());
+            }
+
             // Check whether the target file exists.
             // If it doesn't, we must push a change to create the file.
             if !path.exists() {
+                queue_validation(changeset, file, &path, &file.content);
+
                 let change = FileOperation::Create {
-                    path,
+                    path: path.clone(),
                     content: file.content.clone().into_bytes(),
                     mode: file.mode(),
                     owner: file.config.owner(),
                     group: file.config.group(),
                 };
 
-                changeset.push(PathOperation::File(change));
+                queue_change_hooks(changeset, parent_hooks, &file.config.on_change, &path);
+                changeset.path_operations.push(PathOperation::File(change));
 
                 return Ok(());
             }
@@ -132,11 +225,14 @@ fn handle_entry(root: &PathBuf, entry: &Entry, changeset: &mut Vec<PathOperation
             let mut modified_owner = None;
             let mut modified_group = None;
 
-            // Check whether content matches
-            let content = read_to_string(&path)
-                .map_err(|err| Error::IoPath(path.clone(), "reading file", err))?;
-            if content.trim() != file.content.trim() {
-                modified_content = Some(file.content.clone());
+            // Check whether content matches.
+            // The content of create-only files is never touched once they exist.
+            if !file.config.create_only {
+                let content = read_to_string(&path)
+                    .map_err(|err| Error::IoPath(path.clone(), "reading file", err))?;
+                if content.trim() != file.content.trim() {
+                    modified_content = Some(file.content.clone());
+                }
             }
 
             let metadata = path
@@ -171,6 +267,10 @@ fn handle_entry(root: &PathBuf, entry: &Entry, changeset: &mut Vec<PathOperation
                 || modified_group.is_some()
                 || modified_mode.is_some()
             {
+                if let Some(content) = &modified_content {
+                    queue_validation(changeset, file, &path, content);
+                }
+                queue_change_hooks(changeset, parent_hooks, &file.config.on_change, &path);
                 let change = FileOperation::Modify {
                     path,
                     content: modified_content.map(|str| str.into_bytes()),
@@ -178,15 +278,20 @@ fn handle_entry(root: &PathBuf, entry: &Entry, changeset: &mut Vec<PathOperation
                     owner: modified_owner,
                     group: modified_group,
                 };
-                changeset.push(PathOperation::File(change));
+                changeset.path_operations.push(PathOperation::File(change));
             }
         }
         Entry::Directory(dir) => {
             let path = dir.file_path(root);
 
+            // Changes to any child also trigger the hooks of this directory.
+            let mut hooks = parent_hooks.to_vec();
+            hooks.extend(dir.config.on_change.iter().cloned());
+
             // Check whether the target directory exists.
             // If it doesn't, we must push a change to create the directory.
             if !path.exists() {
+                queue_change_hooks(changeset, &hooks, &[], &path);
                 let change = DirectoryOperation::Create {
                     path,
                     mode: dir.config.mode(),
@@ -194,10 +299,12 @@ fn handle_entry(root: &PathBuf, entry: &Entry, changeset: &mut Vec<PathOperation
                     group: dir.config.group(),
                 };
 
-                changeset.push(PathOperation::Directory(change));
+                changeset
+                    .path_operations
+                    .push(PathOperation::Directory(change));
 
                 for entry in dir.entries.iter() {
-                    handle_entry(root, entry, changeset)?;
+                    handle_entry(root, entry, &hooks, blocks, changeset)?;
                 }
                 return Ok(());
             }
@@ -236,20 +343,292 @@ fn handle_entry(root: &PathBuf, entry: &Entry, changeset: &mut Vec<PathOperation
 
             // If anything has been modified, push a change.
             if modified_owner.is_some() || modified_group.is_some() || modified_mode.is_some() {
+                queue_change_hooks(changeset, &hooks, &[], &path);
                 let change = DirectoryOperation::Modify {
                     path,
                     mode: modified_mode,
                     owner: modified_owner,
                     group: modified_group,
                 };
-                changeset.push(PathOperation::Directory(change));
+                changeset
+                    .path_operations
+                    .push(PathOperation::Directory(change));
             }
 
             for entry in dir.entries.iter() {
-                handle_entry(root, entry, changeset)?;
+                handle_entry(root, entry, &hooks, blocks, changeset)?;
             }
         }
     }
 
     Ok(())
 }
+
+/// Create the operation that's needed to write all managed blocks of a single file.
+///
+/// Blocks are replaced in order, everything around them is left untouched.
+/// As the file itself belongs to the system, its mode and owner are only changed if a block file
+/// explicitly sets them.
+fn handle_blocks(
+    path: &Path,
+    entries: &[(&File, Vec<String>)],
+    changeset: &mut Changeset,
+) -> Result<()> {
+    let Some((first, _)) = entries.first() else {
+        return Ok(());
+    };
+
+    // Files that don't exist yet are created with all blocks.
+    if !path.exists() {
+        let content = entries.iter().fold(String::new(), |content, (file, _)| {
+            replace_block(file, &content)
+        });
+        for (file, hooks) in entries.iter() {
+            queue_validation(changeset, file, path, &content);
+            queue_change_hooks(changeset, hooks, &file.config.on_change, path);
+        }
+
+        let change = FileOperation::Create {
+            path: path.to_path_buf(),
+            content: content.into_bytes(),
+            mode: first.mode(),
+            owner: first.config.owner(),
+            group: first.config.group(),
+        };
+        changeset.path_operations.push(PathOperation::File(change));
+
+        return Ok(());
+    }
+
+    // Only the blocks are compared. The blocks of create-only files are never touched once the
+    // file exists.
+    let content = read_to_string(path)
+        .map_err(|err| Error::IoPath(path.to_path_buf(), "reading file", err))?;
+    let changed: Vec<bool> = entries
+        .iter()
+        .map(|(file, _)| {
+            !file.config.create_only
+                && file
+                    .block_markers()
+                    .and_then(|markers| markers.extract(&content))
+                    .is_none_or(|block| block.trim() != file.content.trim())
+        })
+        .collect();
+
+    let mut modified_content = None;
+    if changed.contains(&true) {
+        let new_content = entries
+            .iter()
+            .zip(changed.iter())
+            .filter(|(_, changed)| **changed)
+            .fold(content.clone(), |content, ((file, _), _)| {
+                replace_block(file, &content)
+            });
+        modified_content = Some(new_content);
+    }
+
+    let metadata = path
+        .metadata()
+        .map_err(|err| Error::IoPath(path.to_path_buf(), "reading metadata", err))?;
+
+    // Check whether permissions match, if any block file sets them.
+    let mut modified_mode = None;
+    if let Some(mode) = entries.iter().find_map(|(file, _)| file.config.mode)
+        && !equal_mode(metadata.permissions().mode(), mode)
+    {
+        modified_mode = Some(mode);
+    }
+
+    // Compare owner, if any block file sets it.
+    let mut modified_owner = None;
+    if let Some(owner) = entries
+        .iter()
+        .find_map(|(file, _)| file.config.owner.clone())
+    {
+        let uid = metadata.uid();
+        let user = NixUser::from_uid(Uid::from_raw(uid))?.context(format!(
+            "Couldn't get username for uid {uid} on file {path:?}"
+        ))?;
+        if user.name != owner {
+            modified_owner = Some(owner);
+        }
+    }
+
+    // Compare group, if any block file sets it.
+    let mut modified_group = None;
+    if let Some(group_name) = entries
+        .iter()
+        .find_map(|(file, _)| file.config.group.clone())
+    {
+        let gid = metadata.gid();
+        let group = NixGroup::from_gid(Gid::from_raw(gid))?
+            .context(format!("Couldn't get groupname for gid {gid}"))?;
+        if group.name != group_name {
+            modified_group = Some(group_name);
+        }
+    }
+
+    // If anything has been modified, push a change.
+    if modified_content.is_some()
+        || modified_owner.is_some()
+        || modified_group.is_some()
+        || modified_mode.is_some()
+    {
+        // Only files whose block changed are validated and trigger their hooks.
+        // Metadata changes concern the whole file, so they trigger all hooks.
+        let metadata_changed =
+            modified_owner.is_some() || modified_group.is_some() || modified_mode.is_some();
+        for ((file, hooks), block_changed) in entries.iter().zip(changed.iter()) {
+            if let (true, Some(content)) = (*block_changed, &modified_content) {
+                queue_validation(changeset, file, path, content);
+            }
+            if *block_changed || metadata_changed {
+                queue_change_hooks(changeset, hooks, &file.config.on_change, path);
+            }
+        }
+
+        let change = FileOperation::Modify {
+            path: path.to_path_buf(),
+            content: modified_content.map(|str| str.into_bytes()),
+            mode: modified_mode,
+            owner: modified_owner,
+            group: modified_group,
+        };
+        changeset.path_operations.push(PathOperation::File(change));
+    }
+
+    Ok(())
+}
+
+/// Replace the block of a file inside the given content.
+fn replace_block(file: &File, content: &str) -> String {
+    match file.block_markers() {
+        Some(markers) => markers.replace(content, &file.content),
+        None => file.content.clone(),
+    }
+}
+
+/// Create the operation that's needed to set all keys of a key/value file.
+///
+/// Settings are applied in order, so later settings override earlier ones.
+fn handle_settings(path: &Path, settings: &[Settings], changeset: &mut Changeset) -> Result<()> {
+    // Files that don't exist yet are created with only the requested keys.
+    if !path.exists() {
+        let content = settings
+            .iter()
+            .fold(String::new(), |content, settings| settings.apply(&content));
+        let change = FileOperation::Create {
+            path: path.to_path_buf(),
+            content: content.into_bytes(),
+            mode: 0o644,
+            owner: CURRENT_USER.clone(),
+            group: CURRENT_GROUP.clone(),
+        };
+        changeset.path_operations.push(PathOperation::File(change));
+
+        return Ok(());
+    }
+
+    let content = read_to_string(path)
+        .map_err(|err| Error::IoPath(path.to_path_buf(), "reading file", err))?;
+    let new_content = settings.iter().fold(content.clone(), |content, settings| {
+        settings.apply(&content)
+    });
+
+    if content.trim() != new_content.trim() {
+        let change = FileOperation::Modify {
+            path: path.to_path_buf(),
+            content: Some(new_content.into_bytes()),
+            mode: None,
+            owner: None,
+            group: None,
+        };
+        changeset.path_operations.push(PathOperation::File(change));
+    }
+
+    Ok(())
+}
+
+/// Queue all hooks that're triggered by a change to the given path.
+fn queue_change_hooks(
+    changeset: &mut Changeset,
+    parent_hooks: &[String],
+    own_hooks: &[String],
+    path: &Path,
+) {
+    for command in parent_hooks.iter().chain(own_hooks.iter()) {
+        changeset.add_change_hook(command, path.to_path_buf());
+    }
+}
+
+/// Queue the validation of a file's new content, if the file requests one.
+fn queue_validation(changeset: &mut Changeset, file: &File, path: &Path, content: &str) {
+    if let Some(command) = &file.config.validate {
+        changeset.validations.push(FileValidation {
+            path: path.to_path_buf(),
+            command: command.clone(),
+            content: content.as_bytes().to_vec(),
+        });
+    }
+}
+
+/// Queue the removal of all unmanaged paths inside of purged directories.
+///
+/// `parent_purged` is set, if any of the parent directories is purged.
+/// `purged_paths` contains all directories that have already been purged.
+fn handle_purge(
+    root: &Path,
+    entry: &Entry,
+    parent_purged: bool,
+    managed_paths: &HashSet<PathBuf>,
+    purged_paths: &mut HashSet<PathBuf>,
+    changeset: &mut Changeset,
+) -> Result<()> {
+    let Entry::Directory(dir) = entry else {
+        return Ok(());
+    };
+
+    let purged = parent_purged || dir.config.purge;
+    let path = dir.file_path(root);
+    if purged && path.is_dir() && purged_paths.insert(path.clone()) {
+        for unmanaged in unmanaged_entries(&path, managed_paths)? {
+            queue_removal(&unmanaged, changeset)?;
+        }
+    }
+
+    for entry in dir.entries.iter() {
+        handle_purge(root, entry, purged, managed_paths, purged_paths, changeset)?;
+    }
+
+    Ok(())
+}
+
+/// Queue the removal of a path.
+/// Directories are removed recursively, starting with their content.
+fn queue_removal(path: &Path, changeset: &mut Changeset) -> Result<()> {
+    let metadata = path
+        .symlink_metadata()
+        .map_err(|err| Error::IoPath(path.to_path_buf(), "reading metadata", err))?;
+
+    if !metadata.is_dir() {
+        let change = FileOperation::Delete {
+            path: path.to_path_buf(),
+        };
+        changeset.path_operations.push(PathOperation::File(change));
+        return Ok(());
+    }
+
+    // Nothing inside an unmanaged directory is managed.
+    for child in unmanaged_entries(path, &HashSet::new())? {
+        queue_removal(&child, changeset)?;
+    }
+
+    let change = DirectoryOperation::Delete {
+        path: path.to_path_buf(),
+    };
+    changeset
+        .path_operations
+        .push(PathOperation::Directory(change));
+
+    Ok(())
+}
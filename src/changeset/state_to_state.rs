@@ -7,7 +7,7 @@ use anyhow::Result;
 use log::info;
 
 use super::{Changeset, PackageUninstall, compiled_state::CompiledState};
-use crate::{state::State, system_state::SystemState};
+use crate::{deployed_state::DeployedState, state::State, system_state::SystemState};
 
 /// Compare a new desired State with a previously deployed state.
 /// This is used to determine any necessary **cleanup** operations, in case the previous deployment
@@ -19,10 +19,10 @@ use crate::{state::State, system_state::SystemState};
 /// be removed, which is why this simplified and easier to handle representation is sufficient.
 pub fn create_changeset(
     system_state: &mut SystemState,
-    old_state: &State,
+    old_state: &DeployedState,
     new_state: &State,
 ) -> Result<Changeset> {
-    let old_compiled_state = CompiledState::from_state(old_state);
+    let old_compiled_state = CompiledState::from_deployed_state(old_state);
     let new_compiled_state = CompiledState::from_state(new_state);
 
     let package_uninstalls =
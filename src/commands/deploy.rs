@@ -4,16 +4,29 @@ use inquire::Confirm;
 use crate::{
     changeset::{Changeset, host_to_state, state_to_host, state_to_state},
     config::bois::Configuration,
+    deployed_state::DeployedState,
     handlers::{
+        hooks::{run_change_hooks, run_post_deploy_hooks, run_pre_deploy_hooks},
         packages::{install_packages, uninstall_packages},
-        paths::handle_path_operations,
+        paths::{handle_path_operations, validate_files},
     },
+    lock::DeployLock,
     state::State,
     system_state::SystemState,
-    ui::{print_package_installs, print_package_uninstalls, print_path_changes},
+    ui::{
+        print_change_hooks,
+        print_deploy_hooks,
+        print_package_installs,
+        print_package_uninstalls,
+        print_path_changes,
+    },
 };
 
-pub fn run_deploy(config: Configuration, dry_run: bool) -> Result<()> {
+pub fn run_deploy(config: Configuration, dry_run: bool, wait: bool) -> Result<()> {
+    // Make sure no other bois process deploys at the same time.
+    // The lock is held until the end of this function.
+    let _lock = DeployLock::acquire(&config, wait)?;
+
     // This struct will hold state of the current system to compare it with the desired state.
     // This doesn't contain all system state, only stuff like packages and system services.
     // It's basically a cache struct, so we don't repeatedly run the same queries all the time.
@@ -26,7 +39,7 @@ pub fn run_deploy(config: Configuration, dry_run: bool) -> Result<()> {
     // This state will be used to determine:
     // - Any changes on the system's files since the last deployment
     // - Cleanup work that might need to be done for the new desired state.
-    let previous_state = State::read_previous(&config)?;
+    let previous_state = DeployedState::read(&config)?;
 
     // Create a new empty changeset.
     // All changes will be appended into this struct
@@ -82,10 +95,32 @@ pub fn run_deploy(config: Configuration, dry_run: bool) -> Result<()> {
 
     changeset.merge(new_changes);
 
+    // ---------- Step 4: Validate new file contents ----------
+    // This is done before anything is executed, so invalid files never touch the system.
+    if !changeset.validations.is_empty() {
+        validate_files(&config, &changeset.validations)?;
+    }
+
     // ------------------- Execution phase -------------------
     // We now start to actually execute commands.
 
-    // ---------- Step 4: Uninstall unwanted packages ----------
+    // Global deploy hooks only run if there's actually something to deploy.
+    let run_deploy_hooks = !changeset.is_empty();
+
+    // ---------- Step 5: Run pre_deploy hooks ----------
+    // A failing pre_deploy hook aborts the deployment, as nothing has been changed yet.
+    if run_deploy_hooks && !desired_state.pre_deploy_hooks.is_empty() {
+        print_deploy_hooks("Pre-deploy hooks", &desired_state.pre_deploy_hooks);
+        println!();
+
+        if !dry_run {
+            run_pre_deploy_hooks(&config, &desired_state.pre_deploy_hooks, &changeset)?;
+        } else {
+            println!("Dry-run. Not running any hooks... yet");
+        }
+    }
+
+    // ---------- Step 6: Uninstall unwanted packages ----------
     if !changeset.package_uninstalls.is_empty() {
         println!("Cleanup changes to be executed:");
 
@@ -99,7 +134,7 @@ pub fn run_deploy(config: Configuration, dry_run: bool) -> Result<()> {
         }
     }
 
-    // ---------- Step 5: Install new packages ----------
+    // ---------- Step 7: Install new packages ----------
     if !changeset.package_installs.is_empty() {
         // Print all package related changes .
         print_package_installs(&changeset.package_installs);
@@ -113,7 +148,7 @@ pub fn run_deploy(config: Configuration, dry_run: bool) -> Result<()> {
         }
     }
 
-    // ---------- Step 6: Execute all path operations ----------
+    // ---------- Step 8: Execute all path operations ----------
     if !changeset.path_operations.is_empty() {
         print_path_changes(&changeset.path_operations, &config)?;
         println!();
@@ -126,9 +161,48 @@ pub fn run_deploy(config: Configuration, dry_run: bool) -> Result<()> {
         }
     }
 
-    // Save the current desired state to disk for the next run.
+    // Failing hooks after this point don't abort the deploy, as all files have already been
+    // deployed. Their errors are collected and reported at the very end.
+    let mut hook_errors = Vec::new();
+
+    // ---------- Step 9: Run hooks of changed paths ----------
+    if !changeset.change_hooks.is_empty() {
+        print_change_hooks(&changeset.change_hooks);
+        println!();
+
+        if !dry_run {
+            if let Err(err) = run_change_hooks(&config, &changeset.change_hooks) {
+                hook_errors.push(format!("{err:#}"));
+            }
+        } else {
+            println!("Dry-run. Not running any hooks... yet");
+        }
+    }
+
+    // ---------- Step 10: Run post_deploy hooks ----------
+    if run_deploy_hooks && !desired_state.post_deploy_hooks.is_empty() {
+        print_deploy_hooks("Post-deploy hooks", &desired_state.post_deploy_hooks);
+        println!();
+
+        if !dry_run {
+            if let Err(err) =
+                run_post_deploy_hooks(&config, &desired_state.post_deploy_hooks, &changeset)
+            {
+                hook_errors.push(format!("{err:#}"));
+            }
+        } else {
+            println!("Dry-run. Not running any hooks... yet");
+        }
+    }
+
+    // Save the manifest of the current desired state to disk for the next run.
+    // This is done even if a hook failed, as all files have already been deployed.
     if !dry_run {
-        desired_state.save()?;
+        DeployedState::from_state(&desired_state).save(&config)?;
+    }
+
+    if !hook_errors.is_empty() {
+        bail!("{}", hook_errors.join("\n"));
     }
 
     Ok(())
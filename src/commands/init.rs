@@ -8,7 +8,10 @@ use anyhow::{Context, Result};
 use serde_yaml::{Mapping, Value};
 
 use crate::{
-    config::bois::{Configuration, Mode},
+    config::{
+        bois::{Configuration, Mode},
+        file::Undefined,
+    },
     templating::render_template,
 };
 
@@ -52,7 +55,13 @@ pub fn run_init(config: Configuration, directory: &Option<PathBuf>) -> Result<()
         serde_yaml::to_value("hostname").unwrap(),
         serde_yaml::to_value(&config.name).unwrap(),
     );
-    let templated_bois_content = render_template(bois_content, &Value::Mapping(variables), &None)?;
+    let templated_bois_content = render_template(
+        bois_content,
+        &Value::Mapping(variables),
+        &None,
+        Undefined::Strict,
+        None,
+    )?;
     let config_path = root_dir.join("bois.yml");
     fs::write(config_path, templated_bois_content)?;
 
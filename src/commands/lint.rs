@@ -0,0 +1,151 @@
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result, bail};
+use serde_yaml::{Mapping, Value};
+
+use super::vars::HostVariables;
+use crate::{
+    config::{bois::Configuration, file::Undefined},
+    error::Error,
+    state::file_parser::read_file,
+    templating::{
+        hosts::Hosts,
+        lint::lint_template,
+        variables::{BUILTIN_SOURCE, VARS_DIR, file_variable_layers, variable_sources},
+    },
+};
+
+/// Files that configure bois itself and are never deployed.
+const CONFIG_FILES: [&str; 8] = [
+    "host.yml",
+    "host.yaml",
+    "group.yml",
+    "group.yaml",
+    "vars.yml",
+    "vars.yaml",
+    "bois.yml",
+    "bois.yaml",
+];
+
+/// Show all variables of all templates of the host and its groups.
+///
+/// Templates are read without rendering them, so this also works for templates that currently
+/// fail to render.
+pub fn lint(config: Configuration) -> Result<()> {
+    // Build the variables the same way they're built for a deployment.
+    let host_variables = HostVariables::read(&config)?;
+
+    // Templates may use the variables of all other hosts.
+    // These are loaded lazily during templating, but we need all of them to check usages.
+    let hosts = Hosts::new(&config.bois_dir);
+    let mut hosts_sources = BTreeMap::new();
+    hosts_sources.insert("hosts".to_string(), BUILTIN_SOURCE.to_string());
+    let mut all_hosts = Mapping::new();
+    for name in hosts.names() {
+        let Some(host_vars) = hosts.host_vars(&name)? else {
+            continue;
+        };
+        let layers = file_variable_layers(&config.bois_dir, &name)?;
+        for (variable, source) in variable_sources(&layers) {
+            hosts_sources.insert(format!("hosts.{name}.{variable}"), source);
+        }
+        all_hosts.insert(name.into(), Value::Mapping(host_vars));
+    }
+
+    // The host's files and each group's files have their own variables.
+    let mut directories = vec![(config.bois_dir.join("hosts").join(&config.name), None)];
+    for name in &host_variables.group_names {
+        directories.push((
+            config.bois_dir.join("groups").join(name),
+            Some(name.as_str()),
+        ));
+    }
+
+    let mut undefined = 0;
+    for (directory, group) in directories {
+        let (mut variables, mut sources) = host_variables.variables(&config, group)?;
+        sources.extend(hosts_sources.clone());
+        if let Value::Mapping(variables) = &mut variables {
+            variables.insert("hosts".into(), Value::Mapping(all_hosts.clone()));
+        }
+
+        let mut files = Vec::new();
+        collect_files(&directory, Path::new(""), &mut files)?;
+
+        for relative_path in files {
+            let file = read_file(&directory, &relative_path)?;
+            if !file.config.template {
+                continue;
+            }
+
+            let path = directory.join(&relative_path);
+            let usages =
+                lint_template(&file.content, &file.config.delimiters, &variables, &sources)
+                    .context(format!("Error for template at {path:?}"))?;
+            if usages.is_empty() {
+                continue;
+            }
+
+            let display_path = path.strip_prefix(&config.bois_dir).unwrap_or(&path);
+            println!("{}", display_path.to_string_lossy());
+            let width = usages
+                .iter()
+                .map(|usage| usage.name.len())
+                .max()
+                .unwrap_or(0);
+            for usage in usages {
+                let source = match usage.source {
+                    Some(source) => source,
+                    None if file.config.undefined == Undefined::Lenient => {
+                        "undefined (lenient)".to_string()
+                    }
+                    None => {
+                        undefined += 1;
+                        "undefined".to_string()
+                    }
+                };
+                println!("  {:width$}  {source}", usage.name);
+            }
+        }
+    }
+
+    if undefined > 0 {
+        bail!("Found {undefined} undefined variables in templates");
+    }
+
+    Ok(())
+}
+
+/// Recursively collect the relative paths of all files in a directory, sorted by path.
+fn collect_files(root: &Path, relative_path: &Path, files: &mut Vec<PathBuf>) -> Result<()> {
+    let directory = root.join(relative_path);
+    let entries = std::fs::read_dir(&directory)
+        .map_err(|err| Error::IoPath(directory.clone(), "reading directory", err))?;
+
+    let mut paths = Vec::new();
+    for entry in entries {
+        let entry = entry.map_err(|err| Error::IoPath(directory.clone(), "reading entry", err))?;
+        paths.push(entry.path());
+    }
+    paths.sort();
+
+    for path in paths {
+        let Some(file_name) = path.file_name() else {
+            continue;
+        };
+        let relative = relative_path.join(file_name);
+        if path.is_dir() {
+            // Only the top-level `vars.d` contains variables.
+            if !(relative_path.as_os_str().is_empty() && file_name == VARS_DIR) {
+                collect_files(root, &relative, files)?;
+            }
+        } else if path.is_file() && !CONFIG_FILES.contains(&file_name.to_string_lossy().as_ref()) {
+            files.push(relative);
+        }
+    }
+
+    Ok(())
+}
@@ -5,13 +5,19 @@ use crate::{args::Subcommand, config::bois::Configuration};
 mod deploy;
 mod diff;
 mod init;
+mod lint;
+mod status;
+mod vars;
 
 pub fn run_subcommand(config: Configuration, subcommand: &Subcommand) -> Result<()> {
     match subcommand {
-        Subcommand::Plan => deploy::run_deploy(config, true),
-        Subcommand::Deploy => deploy::run_deploy(config, false),
+        Subcommand::Plan { wait } => deploy::run_deploy(config, true, *wait),
+        Subcommand::Deploy { wait } => deploy::run_deploy(config, false, *wait),
         Subcommand::Absorb => todo!(),
         Subcommand::Init { directory } => init::run_init(config, directory),
         Subcommand::Diff => diff::diff(config),
+        Subcommand::Status => status::status(config),
+        Subcommand::Lint => lint::lint(config),
+        Subcommand::Vars { group, format } => vars::vars(config, group.as_deref(), *format),
     }
 }
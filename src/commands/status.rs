@@ -0,0 +1,96 @@
+use std::{
+    collections::{BTreeMap, HashSet},
+    path::{Path, PathBuf},
+};
+
+use anyhow::Result;
+
+use crate::{
+    changeset::helper::unmanaged_entries,
+    config::bois::Configuration,
+    state::{State, file::Entry},
+    system_state::SystemState,
+};
+
+pub fn status(config: Configuration) -> Result<()> {
+    unmanaged_paths(&config)
+}
+
+/// Show all files and directories inside of directories managed by bois that aren't provided by
+/// the host or any of its groups.
+fn unmanaged_paths(config: &Configuration) -> Result<()> {
+    let mut system_state = SystemState::new()?;
+
+    // Read the current desired system state from the files in the specified bois directory.
+    let desired_state = State::new(config, &mut system_state)?;
+    let managed_paths = desired_state.managed_paths();
+
+    // Unmanaged paths sorted by their managed directory.
+    // The flag indicates whether the directory is purged.
+    let mut unmanaged = BTreeMap::new();
+    let directories = std::iter::once(&desired_state.host.directory).chain(
+        desired_state
+            .host
+            .groups
+            .iter()
+            .map(|group| &group.directory),
+    );
+    for directory in directories {
+        for entry in directory.entries.iter() {
+            collect_unmanaged(
+                &config.target_dir,
+                entry,
+                false,
+                &managed_paths,
+                &mut unmanaged,
+            )?;
+        }
+    }
+
+    if unmanaged.is_empty() {
+        println!("Files: no unmanaged files in managed directories");
+        return Ok(());
+    }
+
+    for (directory, (purged, paths)) in unmanaged {
+        let note = if purged {
+            " (will be removed on next deploy)"
+        } else {
+            ""
+        };
+        println!("Unmanaged files in {}{note}:", directory.to_string_lossy());
+        for path in paths {
+            println!("- {}", path.to_string_lossy());
+        }
+    }
+
+    Ok(())
+}
+
+/// Recursively collect all unmanaged paths inside of a managed directory.
+fn collect_unmanaged(
+    root: &Path,
+    entry: &Entry,
+    parent_purged: bool,
+    managed_paths: &HashSet<PathBuf>,
+    unmanaged: &mut BTreeMap<PathBuf, (bool, Vec<PathBuf>)>,
+) -> Result<()> {
+    let Entry::Directory(dir) = entry else {
+        return Ok(());
+    };
+
+    let purged = parent_purged || dir.config.purge;
+    let path = dir.file_path(root);
+    if path.is_dir() {
+        let paths = unmanaged_entries(&path, managed_paths)?;
+        if !paths.is_empty() {
+            unmanaged.insert(path, (purged, paths));
+        }
+    }
+
+    for entry in dir.entries.iter() {
+        collect_unmanaged(root, entry, purged, managed_paths, unmanaged)?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,92 @@
+use std::collections::BTreeMap;
+
+use anyhow::{Result, bail};
+use serde_yaml::Value;
+
+use crate::{
+    args::VarsFormat,
+    config::{bois::Configuration, helper::read_yaml},
+    state::host::{HostConfig, resolve_groups},
+    templating::{
+        TemplateContext,
+        variables::{
+            BUILTIN_SOURCE,
+            VariableLayer,
+            annotate_sources,
+            group_variable_layers,
+            host_variable_layers,
+            insert_groups,
+            merge_layers,
+            variable_sources,
+        },
+    },
+};
+
+/// The variable layers of the current host and its enabled groups.
+pub struct HostVariables {
+    pub layers: Vec<VariableLayer>,
+    pub group_names: Vec<String>,
+}
+
+impl HostVariables {
+    /// Build the variable layers the same way they're built for a deployment.
+    pub fn read(config: &Configuration) -> Result<Self> {
+        let host_dir = config.bois_dir.join("hosts").join(&config.name);
+        if !host_dir.exists() {
+            bail!("Couldn't find host config directory at {host_dir:?}");
+        }
+        let host_config = read_yaml::<HostConfig>(&host_dir, "host")?;
+
+        let layers = host_variable_layers(&config.bois_dir, &config.name, &config.overrides)?;
+        let group_names = resolve_groups(
+            &host_config.groups,
+            &merge_layers(&layers),
+            Some(&TemplateContext::new(config)),
+        )?;
+
+        Ok(HostVariables {
+            layers,
+            group_names,
+        })
+    }
+
+    /// Get the variables of the host's files, or of a group's files, and their sources.
+    pub fn variables(
+        &self,
+        config: &Configuration,
+        group: Option<&str>,
+    ) -> Result<(Value, BTreeMap<String, String>)> {
+        let layers = match group {
+            Some(group) => group_variable_layers(&config.bois_dir, group, &self.layers)?,
+            None => self.layers.clone(),
+        };
+
+        let mut variables = merge_layers(&layers);
+        let mut sources = variable_sources(&layers);
+        insert_groups(&mut variables, &self.group_names);
+        sources.insert("boi_groups".to_string(), BUILTIN_SOURCE.to_string());
+
+        Ok((variables, sources))
+    }
+}
+
+/// Print the merged variables of the host or one of its groups.
+/// Every value is annotated with the source it comes from.
+pub fn vars(config: Configuration, group: Option<&str>, format: VarsFormat) -> Result<()> {
+    let host_variables = HostVariables::read(&config)?;
+    if let Some(group) = group
+        && !host_variables.group_names.iter().any(|name| name == group)
+    {
+        bail!("Group {group} isn't enabled for host {}", config.name);
+    }
+
+    let (variables, sources) = host_variables.variables(&config, group)?;
+    let annotated = annotate_sources(&variables, &sources);
+
+    match format {
+        VarsFormat::Yaml => print!("{}", serde_yaml::to_string(&annotated)?),
+        VarsFormat::Json => println!("{}", serde_json::to_string_pretty(&annotated)?),
+    }
+
+    Ok(())
+}
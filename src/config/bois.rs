@@ -9,6 +9,7 @@ use serde::{Deserialize, Serialize};
 use crate::{
     config::helper::{expand_home, find_directory},
     error::Error,
+    templating::{password_managers::secret::SecretProvider, variables::VariableLayer},
 };
 
 /// The current mode we're running in.
@@ -52,6 +53,10 @@ pub struct RawConfiguration {
     /// Determine whether bois is running in system configuration mode or in
     /// user configuration mode.
     pub mode: Option<Mode>,
+
+    /// Password managers and other commands that provide secrets to templates.
+    #[serde(default)]
+    pub secret_providers: Vec<SecretProvider>,
 }
 
 /// All high-level settings that're required to run bois.
@@ -85,6 +90,13 @@ pub struct Configuration {
     /// Determine whether bois is running in system configuration mode or in
     /// user configuration mode.
     pub mode: Mode,
+
+    /// Password managers and other commands that provide secrets to templates.
+    pub secret_providers: Vec<SecretProvider>,
+
+    /// Template variables from the command line and environment, which take precedence over all
+    /// other variables.
+    pub overrides: Vec<VariableLayer>,
 }
 
 impl RawConfiguration {
@@ -193,6 +205,20 @@ If this doesn't work, set the machine's name manually in the global bois.yml."
             },
         };
 
+        // Secret providers are referenced by their name, so names must be unique.
+        for (index, provider) in self.secret_providers.iter().enumerate() {
+            provider.validate()?;
+            if self.secret_providers[..index]
+                .iter()
+                .any(|other| other.name == provider.name)
+            {
+                bail!(
+                    "Found multiple secret providers with name '{}'",
+                    provider.name
+                );
+            }
+        }
+
         Ok(Configuration {
             name,
             bois_dir,
@@ -201,6 +227,8 @@ If this doesn't work, set the machine's name manually in the global bois.yml."
             runtime_dir,
             envs: self.envs,
             mode,
+            secret_providers: self.secret_providers,
+            overrides: Vec::new(),
         })
     }
 
@@ -21,11 +21,28 @@ pub struct DirectoryConfig {
     /// - If it's an relative path, it'll be treated as relative to the default target directory.
     /// - If it's an absolute path, that absolute path will be used.
     path: Option<PathBuf>,
+    /// A condition that decides whether this directory is deployed at all.
+    /// It's evaluated with the host's variables, e.g. `is_laptop`.
+    pub when: Option<String>,
     pub owner: Option<String>,
     pub group: Option<String>,
     /// This is represented as a octal `Oo755` in yaml.
     /// It's automatically parsed to a u32, which can then be used by the std lib.
     pub mode: Option<u32>,
+    /// If this is set, bois owns the whole target directory.
+    /// Any file inside of it that isn't provided by the host or one of its groups is removed.
+    /// This cascades to all subdirectories.
+    #[serde(default)]
+    pub purge: bool,
+    /// If this is set, the files of this directory are fragments, which are assembled into a
+    /// single file. The file is deployed to this directory's path without a trailing `.d`.
+    /// Fragments of several groups are combined.
+    #[serde(default)]
+    pub fragments: bool,
+    /// Commands that should be run after this directory or anything inside of it has been created
+    /// or modified during a deploy.
+    #[serde(default)]
+    pub on_change: Vec<String>,
 }
 
 /// This impl block contains convenience getters for directory metadata, which fall back to
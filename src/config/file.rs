@@ -6,7 +6,10 @@ use std::path::PathBuf;
 use serde::{Deserialize, Serialize};
 
 use super::helper::expand_home;
-use crate::constants::{CURRENT_GROUP, CURRENT_USER};
+use crate::{
+    constants::{CURRENT_GROUP, CURRENT_USER},
+    state::format::DataFormat,
+};
 
 /// The [FileConfig] represents the inlined configuration format that can be
 /// found in source configuration files managed by bois.
@@ -32,10 +35,76 @@ pub struct FileConfig {
     /// See: <https://docs.rs/minijinja/latest/minijinja/syntax/struct.SyntaxConfig.html>
     pub delimiters: Option<Delimiters>,
 
+    /// A condition that decides whether this file is deployed at all.
+    /// It's evaluated with the host's variables, e.g. `is_laptop`.
+    pub when: Option<String>,
+
+    /// Whether this file should exist on the system.
+    /// If it's set to `absent`, the target path is removed whenever it shows up.
+    #[serde(default)]
+    pub state: FileState,
+
+    /// Only deploy this file if it doesn't exist yet.
+    /// Its content is never overwritten afterwards, e.g. for files that're rewritten by the
+    /// application itself. Owner, group and mode are still enforced.
+    #[serde(default)]
+    pub create_only: bool,
+
+    /// Only manage a marked block with this id inside the target file.
+    /// Everything outside of the block is left untouched.
+    pub block: Option<String>,
+    /// The comment prefix that's used for the markers of a managed block.
+    /// Defaults to `#`.
+    pub block_comment: Option<String>,
+
+    /// The original file a `.patch` file is applied to.
+    /// If it isn't set, the original is extracted from the package that owns the target path.
+    pub original: Option<PathBuf>,
+
+    /// If this is set, the file's content is a yaml document, which is converted to this format.
+    /// Templating happens before the conversion.
+    pub format: Option<DataFormat>,
+
     /// Whether this file should be treated as a template.
     /// Defaults to `false` to prevent unwanted behavior.
     #[serde(default)]
     pub template: bool,
+
+    /// How undefined variables are handled while templating.
+    /// Defaults to `strict`, which fails on any undefined variable.
+    #[serde(default)]
+    pub undefined: Undefined,
+
+    /// A command that validates the file's new content before it's deployed.
+    /// The content is written to a temporary file and `%s` is replaced with its path.
+    /// E.g. `visudo -cf %s`.
+    pub validate: Option<String>,
+
+    /// Commands that should be run after this file has been created or modified during a deploy.
+    /// E.g. `sysctl --system` or `systemctl daemon-reload`.
+    #[serde(default)]
+    pub on_change: Vec<String>,
+}
+
+/// The desired state of a file on the system.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FileState {
+    #[default]
+    Present,
+    Absent,
+}
+
+/// How undefined variables are handled while templating.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Undefined {
+    /// Using an undefined variable in any way is an error.
+    /// Use `{% if var is defined %}` to check for optional variables.
+    #[default]
+    Strict,
+    /// Undefined variables are rendered as empty strings and are falsy in conditions.
+    Lenient,
 }
 
 /// This impl block contains convenience getters for file metadata, which fall back to
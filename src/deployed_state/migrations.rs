@@ -0,0 +1,276 @@
+//! Migrations for older versions of the [DeployedState](super::DeployedState) manifest.
+//!
+//! Each migration takes the raw yaml of one version and converts it to the raw yaml of the next
+//! version. Migrations must not depend on any types that might change in the future, which is why
+//! older formats are represented by their own frozen types in here. Migrations also produce these
+//! frozen types, so they never have to change once they've been written.
+use std::{
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result, bail};
+use serde::{Deserialize, Serialize};
+use serde_yaml::Value;
+
+use super::{CURRENT_VERSION, hash_content};
+use crate::{
+    changeset::helper::remove_filetype,
+    config::helper::expand_home,
+    constants::{CURRENT_GROUP, CURRENT_USER},
+    handlers::packages::PackageManager,
+};
+
+/// Run all migrations that're necessary to get from `version` to [CURRENT_VERSION].
+pub fn migrate(mut version: u32, mut value: Value) -> Result<Value> {
+    while version < CURRENT_VERSION {
+        value = match version {
+            1 => v1_to_v2(value)?,
+            2 => v2_to_v3(value)?,
+            3 => v3_to_v4(value)?,
+            _ => unreachable!("No migration exists for state version {version}"),
+        };
+        version += 1;
+    }
+
+    Ok(value)
+}
+
+/// Version 1 was the whole serialized desired `State`, including the full source tree.
+/// Version 2 is the compact manifest with absolute target paths and content hashes.
+fn v1_to_v2(value: Value) -> Result<Value> {
+    let old: v1::State =
+        serde_yaml::from_value(value).context("Failed to read state file of version 1")?;
+
+    let mut state = v2::DeployedState {
+        version: 2,
+        name: old.configuration.name.clone(),
+        ..Default::default()
+    };
+
+    for (manager, packages) in old.packages {
+        state
+            .packages
+            .insert(manager, packages.into_iter().collect());
+    }
+
+    let root = &old.configuration.target_dir;
+    let host_origin = Path::new("hosts").join(&old.configuration.name);
+    v1::add_directory(&mut state.paths, root, &host_origin, &old.host.directory);
+    for group in old.host.groups.iter() {
+        let group_origin = Path::new("groups").join(&group.name);
+        v1::add_directory(&mut state.paths, root, &group_origin, &group.directory);
+    }
+
+    Ok(serde_yaml::to_value(state)?)
+}
+
+/// Version 3 adds `create_only` and `block` to files, as well as the `absent` paths.
+/// All of them are optional, so only the version changes.
+fn v2_to_v3(mut value: Value) -> Result<Value> {
+    let Value::Mapping(mapping) = &mut value else {
+        bail!("State file of version 2 isn't a mapping");
+    };
+    mapping.insert("version".into(), 3.into());
+
+    Ok(value)
+}
+
+/// Version 4 only records the mode, owner and group of block files, if they're set explicitly.
+/// Older manifests always contain them, which would be reported as drift. As we can't tell
+/// whether they've been set explicitly, they're removed.
+fn v3_to_v4(mut value: Value) -> Result<Value> {
+    let Value::Mapping(mapping) = &mut value else {
+        bail!("State file of version 3 isn't a mapping");
+    };
+    mapping.insert("version".into(), 4.into());
+
+    if let Some(Value::Sequence(paths)) = mapping.get_mut("paths") {
+        for path in paths.iter_mut() {
+            let Value::Tagged(tagged) = path else {
+                continue;
+            };
+            let Value::Mapping(file) = &mut tagged.value else {
+                continue;
+            };
+            if tagged.tag == "File" && file.get("block").is_some_and(|block| !block.is_null()) {
+                for key in ["mode", "owner", "group"] {
+                    file.remove(key);
+                }
+            }
+        }
+    }
+
+    Ok(value)
+}
+
+/// The frozen version 2 manifest format.
+mod v2 {
+    use std::collections::{BTreeMap, BTreeSet};
+
+    use super::*;
+    use crate::handlers::services::ServiceManager;
+
+    #[derive(Default, Serialize)]
+    pub struct DeployedState {
+        pub version: u32,
+        pub name: String,
+        pub paths: Vec<DeployedPath>,
+        pub packages: BTreeMap<PackageManager, BTreeSet<String>>,
+        pub services: BTreeMap<ServiceManager, BTreeSet<String>>,
+    }
+
+    #[derive(Serialize)]
+    pub enum DeployedPath {
+        File(DeployedFile),
+        Directory(DeployedDirectory),
+    }
+
+    #[derive(Serialize)]
+    pub struct DeployedFile {
+        pub path: PathBuf,
+        pub origin: PathBuf,
+        pub hash: String,
+        pub mode: u32,
+        pub owner: String,
+        pub group: String,
+    }
+
+    #[derive(Serialize)]
+    pub struct DeployedDirectory {
+        pub path: PathBuf,
+        pub origin: PathBuf,
+        pub mode: u32,
+        pub owner: String,
+        pub group: String,
+    }
+}
+
+/// The frozen subset of the version 1 state format that's needed for the migration.
+mod v1 {
+    use super::{
+        v2::{DeployedDirectory, DeployedFile, DeployedPath},
+        *,
+    };
+
+    #[derive(Deserialize)]
+    pub struct State {
+        pub host: Host,
+        pub configuration: Configuration,
+        #[serde(default)]
+        pub packages: HashMap<PackageManager, HashSet<String>>,
+    }
+
+    #[derive(Deserialize)]
+    pub struct Configuration {
+        pub name: String,
+        pub target_dir: PathBuf,
+    }
+
+    #[derive(Deserialize)]
+    pub struct Host {
+        pub directory: Directory,
+        #[serde(default)]
+        pub groups: Vec<Group>,
+    }
+
+    #[derive(Deserialize)]
+    pub struct Group {
+        pub name: String,
+        pub directory: Directory,
+    }
+
+    #[derive(Deserialize)]
+    pub enum Entry {
+        File(File),
+        Directory(Directory),
+    }
+
+    #[derive(Deserialize)]
+    pub struct Directory {
+        pub relative_path: PathBuf,
+        pub entries: Vec<Entry>,
+        #[serde(default)]
+        pub config: PathConfig,
+    }
+
+    #[derive(Deserialize)]
+    pub struct File {
+        pub relative_path: PathBuf,
+        #[serde(default)]
+        pub config: PathConfig,
+        pub content: String,
+        pub mode: u32,
+    }
+
+    /// The shared subset of the file and directory config.
+    #[derive(Default, Deserialize)]
+    pub struct PathConfig {
+        pub path: Option<PathBuf>,
+        pub rename: Option<String>,
+        pub owner: Option<String>,
+        pub group: Option<String>,
+        pub mode: Option<u32>,
+    }
+
+    impl PathConfig {
+        /// Resolve the target path the same way version 1 did.
+        fn target_path(&self, root: &Path, relative_path: &Path) -> PathBuf {
+            let mut path = match &self.path {
+                Some(path) => {
+                    let path = expand_home(path);
+                    if path.is_absolute() {
+                        path
+                    } else {
+                        root.join(path)
+                    }
+                }
+                None => root.join(relative_path),
+            };
+
+            if let Some(file_name) = &self.rename {
+                path.set_file_name(file_name);
+            }
+
+            path
+        }
+
+        fn owner(&self) -> String {
+            self.owner.clone().unwrap_or(CURRENT_USER.clone())
+        }
+
+        fn group(&self) -> String {
+            self.group.clone().unwrap_or(CURRENT_GROUP.clone())
+        }
+    }
+
+    pub fn add_directory(
+        paths: &mut Vec<DeployedPath>,
+        root: &Path,
+        origin: &Path,
+        directory: &Directory,
+    ) {
+        for entry in directory.entries.iter() {
+            match entry {
+                Entry::File(file) => paths.push(DeployedPath::File(DeployedFile {
+                    path: file.config.target_path(root, &file.relative_path),
+                    origin: origin.join(&file.relative_path),
+                    hash: hash_content(&file.content),
+                    mode: remove_filetype(file.config.mode.unwrap_or(file.mode)),
+                    owner: file.config.owner(),
+                    group: file.config.group(),
+                })),
+                Entry::Directory(dir) => {
+                    paths.push(DeployedPath::Directory(DeployedDirectory {
+                        path: dir.config.target_path(root, &dir.relative_path),
+                        origin: origin.join(&dir.relative_path),
+                        mode: dir.config.mode.unwrap_or(0o755),
+                        owner: dir.config.owner(),
+                        group: dir.config.group(),
+                    }));
+                    add_directory(paths, root, origin, dir);
+                }
+            }
+        }
+    }
+}
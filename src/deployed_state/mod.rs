@@ -0,0 +1,280 @@
+//! This module contains the [DeployedState], which is the compact manifest of everything bois
+//! deployed during its last run.
+//!
+//! In contrast to the desired [State], it doesn't contain the source tree of the bois directory.
+//! It only stores what's needed to detect untracked changes on the system and to determine
+//! cleanup work for the next deployment:
+//! - The target path of every deployed file and directory.
+//! - A hash of each deployed file's content.
+//! - The metadata (mode, owner, group) of each deployed path.
+//! - The source file inside the bois directory each path originates from.
+//! - All deployed packages and services.
+//! - All paths that must not exist on the system.
+//!
+//! The manifest is versioned. Whenever its format changes, including new optional fields,
+//! [CURRENT_VERSION] must be bumped and a migration from the previous version must be added to
+//! the [migrations] module.
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs::{File, read_to_string},
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Result, bail};
+use log::info;
+use serde::{Deserialize, Serialize};
+use serde_yaml::Value;
+use sha2::{Digest, Sha256};
+
+use crate::{
+    changeset::{helper::remove_filetype, managed_block::BlockMarkers},
+    config::{bois::Configuration, file::FileState},
+    error::Error,
+    handlers::{packages::PackageManager, services::ServiceManager},
+    state::{State, directory::Directory, file::Entry},
+};
+
+mod migrations;
+
+/// The version of the manifest format that's written by this version of bois.
+pub const CURRENT_VERSION: u32 = 4;
+
+/// The name of the file in the cache directory the manifest is saved to.
+const STATE_FILE: &str = "deployed_state.yml";
+
+/// The compact manifest of a deployment.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DeployedState {
+    /// The version of the manifest format.
+    pub version: u32,
+    /// The name of the host this state has been deployed for.
+    pub name: String,
+    /// All deployed paths in the order in which they've been deployed.
+    /// Parent directories are always listed before their children.
+    #[serde(default)]
+    pub paths: Vec<DeployedPath>,
+    /// All packages that have been deployed, sorted by package manager.
+    #[serde(default)]
+    pub packages: BTreeMap<PackageManager, BTreeSet<String>>,
+    /// All services that have been enabled, sorted by service manager.
+    #[serde(default)]
+    pub services: BTreeMap<ServiceManager, BTreeSet<String>>,
+    /// All target paths that have been ensured to be absent.
+    #[serde(default)]
+    pub absent: Vec<PathBuf>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DeployedPath {
+    File(DeployedFile),
+    Directory(DeployedDirectory),
+}
+
+impl DeployedPath {
+    /// The absolute target path of this entry.
+    pub fn path(&self) -> &Path {
+        match self {
+            DeployedPath::File(file) => &file.path,
+            DeployedPath::Directory(directory) => &directory.path,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DeployedFile {
+    /// The absolute target path of the file.
+    pub path: PathBuf,
+    /// The source file relative to the bois directory, e.g. `groups/base/sysctl.conf`.
+    pub origin: PathBuf,
+    /// The sha256 hash of the deployed content. See [hash_content].
+    pub hash: String,
+    /// Whether the content is only seeded once and then owned by the system.
+    /// Changes to the content of such files aren't considered drift.
+    #[serde(default)]
+    pub create_only: bool,
+    /// The markers of the managed block, if only a block inside the file is managed.
+    /// In that case, the hash only covers the content of the block.
+    #[serde(default)]
+    pub block: Option<BlockMarkers>,
+    /// The metadata of the file.
+    /// Files with a managed block are owned by the system, so their metadata is only recorded if
+    /// it's set explicitly.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mode: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub owner: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub group: Option<String>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DeployedDirectory {
+    /// The absolute target path of the directory.
+    pub path: PathBuf,
+    /// The source directory relative to the bois directory, e.g. `groups/base/systemd`.
+    pub origin: PathBuf,
+    pub mode: u32,
+    pub owner: String,
+    pub group: String,
+}
+
+/// Hash the content of a file the way it's stored in the manifest.
+///
+/// Leading and trailing whitespace is ignored, the same way it's ignored when comparing
+/// the desired content with the content on the system.
+pub fn hash_content(content: &str) -> String {
+    format!("{:x}", Sha256::digest(content.trim().as_bytes()))
+}
+
+impl DeployedState {
+    /// Compile the manifest for a desired [State].
+    pub fn from_state(state: &State) -> Self {
+        let mut deployed_state = DeployedState {
+            version: CURRENT_VERSION,
+            name: state.configuration.name.clone(),
+            ..Default::default()
+        };
+
+        for (manager, packages) in state.packages.iter() {
+            deployed_state
+                .packages
+                .insert(*manager, packages.iter().cloned().collect());
+        }
+
+        deployed_state.absent = state.absent_paths.clone();
+
+        let root = &state.configuration.target_dir;
+        let host_origin = Path::new("hosts").join(&state.configuration.name);
+        deployed_state.add_directory(root, &host_origin, &state.host.directory);
+
+        for group in state.host.groups.iter() {
+            let group_origin = Path::new("groups").join(&group.name);
+            deployed_state.add_directory(root, &group_origin, &group.directory);
+        }
+
+        deployed_state
+    }
+
+    /// Recursively add all entries of a [Directory] to the manifest.
+    ///
+    /// `origin` is the path of the host/group directory relative to the bois directory.
+    fn add_directory(&mut self, root: &Path, origin: &Path, directory: &Directory) {
+        for entry in directory.entries.iter() {
+            match entry {
+                // Absent files are tracked via their target path in `absent`.
+                Entry::File(file) if file.config.state == FileState::Absent => {}
+                // The metadata of block files is only enforced if it's set explicitly.
+                Entry::File(file) if file.block_markers().is_some() => {
+                    self.paths.push(DeployedPath::File(DeployedFile {
+                        path: file.file_path(root),
+                        origin: origin.join(&file.relative_path),
+                        hash: hash_content(&file.content),
+                        create_only: file.config.create_only,
+                        block: file.block_markers(),
+                        mode: file.config.mode.map(remove_filetype),
+                        owner: file.config.owner.clone(),
+                        group: file.config.group.clone(),
+                    }));
+                }
+                Entry::File(file) => {
+                    self.paths.push(DeployedPath::File(DeployedFile {
+                        path: file.file_path(root),
+                        origin: origin.join(&file.relative_path),
+                        hash: hash_content(&file.content),
+                        create_only: file.config.create_only,
+                        block: None,
+                        mode: Some(remove_filetype(file.mode())),
+                        owner: Some(file.config.owner()),
+                        group: Some(file.config.group()),
+                    }));
+                }
+                Entry::Directory(dir) => {
+                    self.paths.push(DeployedPath::Directory(DeployedDirectory {
+                        path: dir.file_path(root),
+                        origin: origin.join(&dir.relative_path),
+                        mode: dir.config.mode(),
+                        owner: dir.config.owner(),
+                        group: dir.config.group(),
+                    }));
+                    self.add_directory(root, origin, dir);
+                }
+            }
+        }
+    }
+
+    /// Try to read the manifest of a previous deployment.
+    /// This state will be used to determine:
+    /// - Any changes on the system's files since the last deployment
+    /// - Cleanup work that might need to be done for the new desired state.
+    ///
+    /// Manifests that have been written by older versions of bois are migrated to the current
+    /// format.
+    ///
+    /// Will return a Ok(None), if no previous state could be found.
+    pub fn read(config: &Configuration) -> Result<Option<Self>> {
+        // Get the path for the deployed state.
+        let path = config.cache_dir.join(STATE_FILE);
+        info!("Looking for previous state file at {path:?}");
+
+        // Return None if we cannot find any file.
+        if !path.exists() || !path.is_file() {
+            info!("No state file found. Assuming first deployment.");
+            return Ok(None);
+        };
+
+        info!("Found previous deployed state at: {path:?}");
+
+        let content = read_to_string(&path)
+            .map_err(|err| Error::IoPath(path.clone(), "reading state file", err))?;
+        let value: Value = serde_yaml::from_str(&content)
+            .map_err(|err| Error::Deserialization(path.clone(), err))?;
+
+        // State files without a version have been written before versioning was introduced.
+        let version = match value.get("version") {
+            None => 1,
+            Some(version) => match version.as_u64() {
+                Some(version) => version as u32,
+                None => bail!("Found invalid version {version:?} in state file {path:?}"),
+            },
+        };
+
+        if version > CURRENT_VERSION {
+            bail!(
+                "The state file at {path:?} has version {version}, but this version of bois only \
+                supports up to version {CURRENT_VERSION}. Please update bois."
+            );
+        }
+
+        let value = migrations::migrate(version, value)?;
+        let state =
+            serde_yaml::from_value(value).map_err(|err| Error::Deserialization(path, err))?;
+
+        Ok(Some(state))
+    }
+
+    /// Save the manifest to the cache directory. \
+    /// Read the `Self::read` docs on why we need this file at all.
+    pub fn save(&self, config: &Configuration) -> Result<(), Error> {
+        let path = config.cache_dir.join(STATE_FILE);
+        info!("Writing deployed state to {path:?}");
+
+        // Serialize the manifest and write it to disk
+        let content = match serde_yaml::to_string(self) {
+            Ok(content) => content,
+            Err(error) => {
+                return Err(Error::Generic(format!(
+                    "Deployed state serialization failed:\n{error}"
+                )));
+            }
+        };
+
+        // Write the serialized content to the file.
+        let mut file = File::create(&path)
+            .map_err(|err| Error::IoPath(path.clone(), "creating state file", err))?;
+        file.write_all(content.as_bytes())
+            .map_err(|err| Error::IoPath(path, "writing state file", err))?;
+
+        Ok(())
+    }
+}
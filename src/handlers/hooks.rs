@@ -0,0 +1,152 @@
+use std::{collections::HashMap, process::Command};
+
+use anyhow::{Result, bail};
+use crossterm::style::Stylize;
+
+use crate::{
+    changeset::{ChangeHook, Changeset, DirectoryOperation, FileOperation, PathOperation},
+    config::bois::Configuration,
+    error::Error,
+};
+
+/// Run all change hooks in the order in which they've been queued.
+///
+/// All hooks are run, even if some of them fail, as the files they belong to have already been
+/// deployed at this point. Failures are collected and reported at the end.
+pub fn run_change_hooks(config: &Configuration, hooks: &[ChangeHook]) -> Result<()> {
+    let mut failures = Vec::new();
+    for hook in hooks {
+        if let Err(err) = run_command(config, &hook.command, &HashMap::new()) {
+            failures.push(format!("{err:#}"));
+        }
+    }
+
+    if !failures.is_empty() {
+        bail!("Some change hooks failed:\n{}", failures.join("\n"));
+    }
+
+    Ok(())
+}
+
+/// Run the global `pre_deploy` hooks.
+///
+/// These run before anything is changed on the system, so the first failing hook aborts the
+/// deployment.
+pub fn run_pre_deploy_hooks(
+    config: &Configuration,
+    hooks: &[String],
+    changeset: &Changeset,
+) -> Result<()> {
+    let envs = changeset_envs(config, changeset, "pre_deploy");
+    for hook in hooks {
+        run_command(config, hook, &envs)?;
+    }
+
+    Ok(())
+}
+
+/// Run the global `post_deploy` hooks.
+///
+/// Everything has already been deployed at this point, so all hooks are run and failures are
+/// reported at the end.
+pub fn run_post_deploy_hooks(
+    config: &Configuration,
+    hooks: &[String],
+    changeset: &Changeset,
+) -> Result<()> {
+    let envs = changeset_envs(config, changeset, "post_deploy");
+    let mut failures = Vec::new();
+    for hook in hooks {
+        if let Err(err) = run_command(config, hook, &envs) {
+            failures.push(format!("{err:#}"));
+        }
+    }
+
+    if !failures.is_empty() {
+        bail!("Some post_deploy hooks failed:\n{}", failures.join("\n"));
+    }
+
+    Ok(())
+}
+
+/// Summarize a changeset as environment variables for deploy hooks.
+///
+/// - `BOIS_HOOK`: Either `pre_deploy` or `post_deploy`.
+/// - `BOIS_HOST`: The name of the current host.
+/// - `BOIS_PACKAGE_INSTALLS`: Space separated list of packages that're installed.
+/// - `BOIS_PACKAGE_UNINSTALLS`: Space separated list of packages that're uninstalled.
+/// - `BOIS_CREATED_PATHS`, `BOIS_MODIFIED_PATHS`, `BOIS_DELETED_PATHS`: Newline separated lists of
+///   paths, by kind of path operation.
+pub fn changeset_envs(
+    config: &Configuration,
+    changeset: &Changeset,
+    hook: &str,
+) -> HashMap<String, String> {
+    let mut created = Vec::new();
+    let mut modified = Vec::new();
+    let mut deleted = Vec::new();
+    for op in changeset.path_operations.iter() {
+        let (list, path) = match op {
+            PathOperation::File(FileOperation::Create { path, .. })
+            | PathOperation::Directory(DirectoryOperation::Create { path, .. }) => {
+                (&mut created, path)
+            }
+            PathOperation::File(FileOperation::Modify { path, .. })
+            | PathOperation::Directory(DirectoryOperation::Modify { path, .. }) => {
+                (&mut modified, path)
+            }
+            PathOperation::File(FileOperation::Delete { path })
+            | PathOperation::Directory(DirectoryOperation::Delete { path }) => (&mut deleted, path),
+        };
+        list.push(path.to_string_lossy().to_string());
+    }
+
+    let installs: Vec<&str> = changeset
+        .package_installs
+        .iter()
+        .map(|pkg| pkg.name.as_str())
+        .collect();
+    let uninstalls: Vec<&str> = changeset
+        .package_uninstalls
+        .iter()
+        .map(|pkg| pkg.name.as_str())
+        .collect();
+
+    HashMap::from([
+        ("BOIS_HOOK".to_string(), hook.to_string()),
+        ("BOIS_HOST".to_string(), config.name.clone()),
+        ("BOIS_PACKAGE_INSTALLS".to_string(), installs.join(" ")),
+        ("BOIS_PACKAGE_UNINSTALLS".to_string(), uninstalls.join(" ")),
+        ("BOIS_CREATED_PATHS".to_string(), created.join("\n")),
+        ("BOIS_MODIFIED_PATHS".to_string(), modified.join("\n")),
+        ("BOIS_DELETED_PATHS".to_string(), deleted.join("\n")),
+    ])
+}
+
+/// Run a single hook command via `sh -c`.
+///
+/// `envs` are passed to the command on top of the user-provided environment variables.
+pub fn run_command(
+    config: &Configuration,
+    command: &str,
+    envs: &HashMap<String, String>,
+) -> Result<()> {
+    println!("{} {command}", "Running".blue());
+    let output = Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .envs(&config.envs)
+        .envs(envs)
+        .output()
+        .map_err(|err| Error::Process("sh", err))?;
+
+    if !output.status.success() {
+        bail!(
+            "Hook '{command}' failed:\nStdout: {}\nStderr: {}",
+            String::from_utf8_lossy(&output.stdout),
+            String::from_utf8_lossy(&output.stderr),
+        );
+    }
+
+    Ok(())
+}
@@ -1,3 +1,4 @@
+pub mod hooks;
 pub mod packages;
 pub mod paths;
 pub mod services;
@@ -1,9 +1,9 @@
-use std::{collections::HashSet, process::Command};
+use std::{collections::HashSet, fs::read_dir, path::Path, process::Command};
 
 use anyhow::{Context, Result, bail};
 use log::info;
 
-use crate::{handlers::packages::PackageManager, system_state::SystemState};
+use crate::{error::Error, handlers::packages::PackageManager, system_state::SystemState};
 
 /// Install a package via pacman.
 /// We install packages in `--asexplicit` mode, so they show up as exiplictly installed packages.
@@ -208,3 +208,82 @@ pub fn detect_installed_groups() -> Result<HashSet<String>> {
 
     Ok(groups)
 }
+
+/// The directory in which pacman caches downloaded packages.
+const PACKAGE_CACHE: &str = "/var/cache/pacman/pkg";
+
+/// Get the original content of a file, as it's shipped by the package that owns it.
+///
+/// The file is extracted from the currently installed version of that package in pacman's
+/// package cache.
+pub fn original_file(path: &Path) -> Result<String> {
+    // Find the package that owns the file.
+    let output = Command::new("pacman")
+        .args(["--query", "--quiet", "--owns"])
+        .arg(path)
+        .output()
+        .context("Failed to query owner of file via pacman")?;
+    if !output.status.success() {
+        bail!(
+            "Couldn't find the package that owns {path:?}:\n{}",
+            String::from_utf8_lossy(&output.stderr),
+        );
+    }
+    let package = String::from_utf8_lossy(&output.stdout).trim().to_string();
+
+    // Get the installed version of that package, which is printed as `name version`.
+    let output = Command::new("pacman")
+        .args(["--query", &package])
+        .output()
+        .context("Failed to query package version via pacman")?;
+    if !output.status.success() {
+        bail!(
+            "Couldn't get version of package {package}:\n{}",
+            String::from_utf8_lossy(&output.stderr),
+        );
+    }
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let version = stdout
+        .split_whitespace()
+        .nth(1)
+        .context(format!("Couldn't parse version of package {package}"))?;
+
+    // Package archives are named `{name}-{version}-{arch}.pkg.tar.{compression}`.
+    let prefix = format!("{package}-{version}-");
+    let entries = read_dir(PACKAGE_CACHE)
+        .map_err(|err| Error::IoPath(PACKAGE_CACHE.into(), "reading package cache", err))?;
+    let mut archive = None;
+    for entry in entries {
+        let entry = entry
+            .map_err(|err| Error::IoPath(PACKAGE_CACHE.into(), "reading package cache", err))?;
+        let name = entry.file_name().to_string_lossy().to_string();
+        if name.starts_with(&prefix) && name.contains(".pkg.tar") && !name.ends_with(".sig") {
+            archive = Some(entry.path());
+            break;
+        }
+    }
+    let Some(archive) = archive else {
+        bail!(
+            "Couldn't find package {package} {version} in {PACKAGE_CACHE}.\nThe original file is \
+             taken from the package archive, which must be in the package cache. It may have been \
+             removed by `paccache -r`, run `pacman -Sw {package}` to download it again."
+        );
+    };
+
+    // Paths inside of the package archives are relative to the root directory.
+    let inner_path = path.strip_prefix("/").unwrap_or(path);
+    let output = Command::new("bsdtar")
+        .arg("-xOf")
+        .arg(&archive)
+        .arg(inner_path)
+        .output()
+        .context("Failed to extract file from package via bsdtar")?;
+    if !output.status.success() {
+        bail!(
+            "Couldn't extract {path:?} from {archive:?}:\n{}",
+            String::from_utf8_lossy(&output.stderr),
+        );
+    }
+
+    String::from_utf8(output.stdout).context(format!("Original of {path:?} isn't valid utf-8"))
+}
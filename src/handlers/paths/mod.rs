@@ -4,6 +4,9 @@ use file::{create_file, modify_file, remove_file};
 
 mod directory;
 mod file;
+mod validation;
+
+pub use validation::validate_files;
 
 use crate::{changeset::PathOperation, system_state::SystemState};
 
@@ -0,0 +1,94 @@
+use std::{
+    fs::{File, remove_file},
+    io::Write,
+    os::unix::fs::OpenOptionsExt,
+    process::Command,
+};
+
+use anyhow::{Result, bail};
+use crossterm::style::Stylize;
+
+use crate::{changeset::FileValidation, config::bois::Configuration, error::Error};
+
+/// Run all validation commands against the new content of their files.
+///
+/// This must be called before any operation is executed, so that a single invalid file aborts
+/// the deployment before the system is touched.
+pub fn validate_files(config: &Configuration, validations: &[FileValidation]) -> Result<()> {
+    let mut failures = Vec::new();
+    for validation in validations {
+        if let Err(err) = validate_file(config, validation) {
+            failures.push(format!("{err:#}"));
+        }
+    }
+
+    if !failures.is_empty() {
+        bail!(
+            "Validation failed, nothing has been deployed:\n{}",
+            failures.join("\n")
+        );
+    }
+
+    Ok(())
+}
+
+/// Write the new content of a file to a temporary file and run the validation command on it.
+///
+/// Every `%s` in the command is replaced with the path of the temporary file.
+/// If the command doesn't contain `%s`, the path is appended as the last argument.
+fn validate_file(config: &Configuration, validation: &FileValidation) -> Result<()> {
+    let path = &validation.path;
+    println!("{} {path:?}", "Validating".blue());
+
+    let file_name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let temp_path = config
+        .runtime_dir
+        .join(format!("bois_validate_{file_name}"));
+
+    // Write file to a temporary file in the user's runtime directory.
+    // The content might contain secrets, so only we are allowed to read it.
+    {
+        let mut temp_file = File::options()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .mode(0o600)
+            .open(&temp_path)
+            .map_err(|err| Error::IoPath(temp_path.clone(), "opening validation file.", err))?;
+        temp_file
+            .write_all(&validation.content)
+            .map_err(|err| Error::IoPath(temp_path.clone(), "writing validation file.", err))?;
+    }
+
+    let quoted_path = format!("'{}'", temp_path.to_string_lossy().replace('\'', r"'\''"));
+    let command = if validation.command.contains("%s") {
+        validation.command.replace("%s", &quoted_path)
+    } else {
+        format!("{} {quoted_path}", validation.command)
+    };
+
+    let output = Command::new("sh")
+        .arg("-c")
+        .arg(&command)
+        .envs(&config.envs)
+        .output();
+
+    // Always clean up the temporary file, independent of the result.
+    remove_file(&temp_path)
+        .map_err(|err| Error::IoPath(temp_path.clone(), "removing validation file", err))?;
+
+    let output = output.map_err(|err| Error::Process("sh", err))?;
+    if !output.status.success() {
+        bail!(
+            "Validation '{}' of {path:?} failed:\nStdout: {}\nStderr: {}",
+            validation.command,
+            String::from_utf8_lossy(&output.stdout),
+            String::from_utf8_lossy(&output.stderr),
+        );
+    }
+
+    Ok(())
+}
@@ -5,8 +5,10 @@ pub mod changeset;
 pub mod commands;
 pub mod config;
 pub mod constants;
+pub mod deployed_state;
 pub mod error;
 pub mod handlers;
+pub mod lock;
 pub mod state;
 pub mod system_state;
 pub mod templating;
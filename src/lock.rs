@@ -0,0 +1,105 @@
+//! This module contains the [DeployLock], which prevents multiple bois processes from deploying to
+//! the same system at the same time.
+//!
+//! Without it, a deployment that's triggered by a systemd timer could race with a manual
+//! `bois deploy`. Both would read the previously deployed state, apply their path operations and
+//! then overwrite each other's state.
+use std::{
+    fs::{File, create_dir_all, read_to_string},
+    io::{Seek, Write},
+    path::Path,
+};
+
+use anyhow::{Result, bail};
+use log::info;
+use nix::{
+    errno::Errno,
+    fcntl::{Flock, FlockArg},
+};
+
+use crate::{config::bois::Configuration, error::Error};
+
+/// The name of the lock file inside the cache directory.
+const LOCK_FILE: &str = "bois.lock";
+
+/// An exclusive lock on the cache directory, which contains the deployed state.
+///
+/// The lock is held until this struct is dropped.
+/// While it's held, the lock file contains the PID and command of the holding process.
+pub struct DeployLock {
+    _file: Flock<File>,
+}
+
+impl DeployLock {
+    /// Take the exclusive deploy lock.
+    ///
+    /// If another bois process currently holds the lock, this either fails with an error that
+    /// describes that process or, if `wait` is set, blocks until the lock is released.
+    pub fn acquire(config: &Configuration, wait: bool) -> Result<DeployLock> {
+        let path = config.cache_dir.join(LOCK_FILE);
+        info!("Acquiring deploy lock at {path:?}");
+
+        // The cache directory isn't created automatically in system mode.
+        create_dir_all(&config.cache_dir).map_err(|err| {
+            Error::IoPath(config.cache_dir.clone(), "creating cache directory", err)
+        })?;
+
+        // Don't truncate the file yet, as it still contains the info about a potential holder.
+        let file = File::options()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&path)
+            .map_err(|err| Error::IoPath(path.clone(), "opening lock file", err))?;
+
+        let mut lock = match Flock::lock(file, FlockArg::LockExclusiveNonblock) {
+            Ok(lock) => lock,
+            Err((file, Errno::EWOULDBLOCK)) => {
+                let holder = describe_holder(&path);
+                if !wait {
+                    bail!(
+                        "Another bois process is already running ({holder}).\n\
+                        Wait for it to finish or use `--wait` to wait for it automatically."
+                    );
+                }
+
+                println!("Waiting for other bois process to finish ({holder})");
+                Flock::lock(file, FlockArg::LockExclusive).map_err(|(_, errno)| {
+                    Error::IoPath(path.clone(), "waiting for lock", errno.into())
+                })?
+            }
+            Err((_, errno)) => {
+                return Err(Error::IoPath(path, "acquiring lock", errno.into()).into());
+            }
+        };
+
+        // We now hold the lock. Record who we are, so others know who they're waiting for.
+        let command: Vec<String> = std::env::args().collect();
+        let holder = format!(
+            "pid: {}\ncommand: {}\n",
+            std::process::id(),
+            command.join(" ")
+        );
+        lock.set_len(0)
+            .and_then(|_| lock.rewind())
+            .and_then(|_| lock.write_all(holder.as_bytes()))
+            .map_err(|err| Error::IoPath(path.clone(), "writing lock file", err))?;
+
+        Ok(DeployLock { _file: lock })
+    }
+}
+
+/// Read the info about the process that currently holds the lock from the lock file.
+fn describe_holder(path: &Path) -> String {
+    let Ok(content) = read_to_string(path) else {
+        return "unknown process".to_string();
+    };
+
+    let description: Vec<&str> = content.lines().filter(|line| !line.is_empty()).collect();
+    if description.is_empty() {
+        return "unknown process".to_string();
+    }
+
+    description.join(", ")
+}
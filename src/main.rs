@@ -4,7 +4,13 @@
 #![allow(dead_code)]
 
 use anyhow::Result;
-use bois::{CONFIG, args::Arguments, commands::run_subcommand, config::bois::RawConfiguration};
+use bois::{
+    CONFIG,
+    args::Arguments,
+    commands::run_subcommand,
+    config::bois::RawConfiguration,
+    templating::variables::override_layers,
+};
 use clap::Parser;
 use log::{LevelFilter, debug};
 use pretty_env_logger::env_logger::Builder;
@@ -21,7 +27,10 @@ fn main() -> Result<()> {
     // Build the final configuration based on the values from the config file.
     // All other values are populated with default values.
     let raw_config = RawConfiguration::read(&args.config)?;
-    let config = raw_config.build_configuration()?;
+    let mut config = raw_config.build_configuration()?;
+
+    // Variable overrides from the command line and environment.
+    config.overrides = override_layers(&args.vars, &args.vars_files)?;
 
     debug!("Running with the following config:\n{config:#?}");
 
@@ -1,6 +1,6 @@
 use std::path::{Path, PathBuf};
 
-use anyhow::Result;
+use anyhow::{Context, Result};
 use log::trace;
 use serde::{Deserialize, Serialize};
 
@@ -8,6 +8,7 @@ use super::file::*;
 use crate::{
     config::{directory::DirectoryConfig, helper::read_yaml},
     error::Error,
+    templating::{TemplateContext, evaluate_condition, render_path},
 };
 
 #[derive(Clone, Debug, Default, Deserialize, Serialize)]
@@ -58,12 +59,16 @@ impl Directory {
 ///     bois configuration directory. `root + relative_path => actual path`
 ///     This is used to determine the destination path, relative to the target directory.
 /// `path_override`
+/// `context` The parts of the bois configuration that're used while templating.
+///
+/// Returns `None`, if the directory's `when` condition isn't met.
 pub fn read_directory(
     root: &Path,
     relative_path: &Path,
     mut path_override: Option<PathBuf>,
     template_vars: &serde_yaml::Value,
-) -> Result<Directory> {
+    context: Option<&TemplateContext>,
+) -> Result<Option<Directory>> {
     let directory_path = root.join(relative_path);
     trace!("Entered directory {directory_path:?}");
 
@@ -73,6 +78,24 @@ pub fn read_directory(
         directory_config = read_yaml::<DirectoryConfig>(&directory_path, "bois")?;
     }
 
+    // Skip the whole directory, if its condition isn't met.
+    if let Some(condition) = &directory_config.when {
+        let deploy = evaluate_condition(condition, template_vars, context).context(format!(
+            "Error for condition of directory at {directory_path:?}"
+        ))?;
+        if !deploy {
+            trace!("Skipping directory {directory_path:?} due to its condition");
+            return Ok(None);
+        }
+    }
+
+    // Paths may be templates, e.g. `/home/{{ user }}/.config`.
+    if let Some(path) = directory_config.path() {
+        let rendered = render_path(&path.to_string_lossy(), template_vars, context)
+            .context(format!("Error for path of directory at {directory_path:?}"))?;
+        directory_config.override_path(PathBuf::from(rendered));
+    }
+
     // Check if there's a new path override in this config.
     // If it is, we set the override, which will be passed to all child entries.
     if let Some(path) = directory_config.path() {
@@ -110,8 +133,9 @@ pub fn read_directory(
             &mut directory,
             path_override.clone(),
             template_vars,
+            context,
         )?;
     }
 
-    Ok(directory)
+    Ok(Some(directory))
 }
@@ -8,7 +8,12 @@ use log::{info, trace};
 use serde::{Deserialize, Serialize};
 
 use super::directory::*;
-use crate::{config::file::FileConfig, state::file_parser::read_file, templating::render_template};
+use crate::{
+    changeset::managed_block::BlockMarkers,
+    config::file::FileConfig,
+    state::{file_parser::read_file, format::DataFormat, patch::PATCH_EXTENSION},
+    templating::{TemplateContext, evaluate_condition, render_path, render_template},
+};
 
 #[derive(Clone, Debug, Deserialize, Serialize)]
 #[allow(clippy::large_enum_variant)]
@@ -71,6 +76,22 @@ impl File {
     pub fn mode(&self) -> u32 {
         self.config.mode.unwrap_or(self.mode)
     }
+
+    /// Whether this file is a patch, which is applied to the original file of its target path.
+    pub fn is_patch(&self) -> bool {
+        self.relative_path
+            .extension()
+            .is_some_and(|extension| extension == PATCH_EXTENSION)
+    }
+
+    /// Return the markers of the managed block, if only a block inside the file is managed.
+    pub fn block_markers(&self) -> Option<BlockMarkers> {
+        let comment = self.config.block_comment.as_deref().unwrap_or("#");
+        self.config
+            .block
+            .as_ref()
+            .map(|id| BlockMarkers::new(id, comment))
+    }
 }
 
 /// Process a directory entry.
@@ -84,6 +105,7 @@ impl File {
 /// `entry` The actual file entry.
 /// `directory` The representation of the directory we're currently processing.
 ///             All files/directories must be added to this `Directory`.
+/// `context` The parts of the bois configuration that're used while templating.
 pub fn read_entry(
     root: &Path,
     relative_path: &Path,
@@ -91,25 +113,60 @@ pub fn read_entry(
     directory: &mut Directory,
     mut path_override: Option<PathBuf>,
     template_vars: &serde_yaml::Value,
+    context: Option<&TemplateContext>,
 ) -> Result<()> {
     let file_name = entry.file_name();
+    let source_path = relative_path.join(&file_name);
 
-    let relative_path = relative_path.join(&file_name);
+    // File names may be templates, e.g. `{{ host }}.conf`.
+    let target_name = render_path(&file_name.to_string_lossy(), template_vars, context)
+        .context(format!("Error for file name at {source_path:?}"))?;
 
     // If there's an active override, adjust the override for the next level.
+    // Otherwise, templated names need an override, as the target path can no longer be derived
+    // from the source path.
     if let Some(path) = path_override {
-        path_override = Some(path.join(&file_name));
+        path_override = Some(path.join(&target_name));
+    } else if target_name != file_name.to_string_lossy() {
+        path_override = Some(relative_path.join(&target_name));
     }
 
+    let relative_path = source_path;
+
     // Recursively discover new directories
     let path = entry.path();
     if path.is_dir() {
-        let sub_directory = read_directory(root, &relative_path, path_override, template_vars)?;
-        directory.entries.push(Entry::Directory(sub_directory));
+        let sub_directory =
+            read_directory(root, &relative_path, path_override, template_vars, context)?;
+        if let Some(sub_directory) = sub_directory {
+            directory.entries.push(Entry::Directory(sub_directory));
+        }
     } else if path.is_file() {
         trace!("Reading file {path:?}");
         let mut file = read_file(root, &relative_path)?;
 
+        // Skip the file, if its condition isn't met.
+        if let Some(condition) = &file.config.when {
+            let deploy = evaluate_condition(condition, template_vars, context)
+                .context(format!("Error for condition of file at {path:?}"))?;
+            if !deploy {
+                trace!("Skipping file {path:?} due to its condition");
+                return Ok(());
+            }
+        }
+
+        // Paths may be templates as well.
+        if let Some(file_path) = file.config.path() {
+            let rendered = render_path(&file_path.to_string_lossy(), template_vars, context)
+                .context(format!("Error for path of file at {path:?}"))?;
+            file.config.override_path(PathBuf::from(rendered));
+        }
+        if let Some(rename) = &file.config.rename {
+            let rendered = render_path(rename, template_vars, context)
+                .context(format!("Error for rename of file at {path:?}"))?;
+            file.config.rename = Some(rendered);
+        }
+
         // Check if there's an active path override from a parent directory.
         // If the file doesn't have its own override, use the one from the parent.
         if let Some(path_override) = path_override {
@@ -118,14 +175,47 @@ pub fn read_entry(
             }
         }
 
+        // Patches are deployed to the path of the file they patch.
+        // E.g. `pacman.conf.patch` is deployed to `pacman.conf`.
+        if file.is_patch() && file.config.rename.is_none() {
+            let file_name = Path::new(&target_name)
+                .file_stem()
+                .map(|stem| stem.to_string_lossy());
+            file.config.rename = file_name.map(|name| name.to_string());
+        }
+
         // Perform templating, if enabled
         // Otherwise return the raw content.
         if file.config.template {
             info!("Starting templating for file {path:?}");
-            file.content = render_template(&file.content, template_vars, &file.config.delimiters)
-                .context(format!("Error for template at {path:?}"))?
+            file.content = render_template(
+                &file.content,
+                template_vars,
+                &file.config.delimiters,
+                file.config.undefined,
+                context,
+            )
+            .context(format!("Error for template at {path:?}"))?
         };
 
+        // Convert yaml data files to their target format.
+        // E.g. `alacritty.yml` with `format: toml` is deployed as `alacritty.toml`.
+        if let Some(format) = file.config.format {
+            let value = DataFormat::Yaml
+                .parse(&file.content)
+                .context(format!("Error for data file at {path:?}"))?;
+            file.content = format
+                .serialize(&value)
+                .context(format!("Error for data file at {path:?}"))?;
+
+            if file.config.rename.is_none()
+                && DataFormat::from_path(&file.relative_path) == Some(DataFormat::Yaml)
+            {
+                let file_name = Path::new(&target_name).with_extension(format.extension());
+                file.config.rename = Some(file_name.to_string_lossy().to_string());
+            }
+        }
+
         directory.entries.push(Entry::File(file));
     }
 
@@ -0,0 +1,188 @@
+//! This module contains the structured data formats bois is able to read and write.
+//!
+//! All formats are parsed into a [serde_yaml::Value], which allows merging documents of the
+//! same format.
+use std::path::Path;
+
+use anyhow::{Context, Result, bail};
+use serde::{Deserialize, Serialize};
+use serde_yaml::{Mapping, Value};
+
+/// A structured data format.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DataFormat {
+    Json,
+    Toml,
+    Yaml,
+    Ini,
+}
+
+impl DataFormat {
+    /// Detect the format of a file by its extension.
+    pub fn from_path(path: &Path) -> Option<Self> {
+        match path.extension()?.to_str()? {
+            "json" => Some(DataFormat::Json),
+            "toml" => Some(DataFormat::Toml),
+            "yml" | "yaml" => Some(DataFormat::Yaml),
+            "ini" => Some(DataFormat::Ini),
+            _ => None,
+        }
+    }
+
+    /// The file extension of this format.
+    pub fn extension(&self) -> &'static str {
+        match self {
+            DataFormat::Json => "json",
+            DataFormat::Toml => "toml",
+            DataFormat::Yaml => "yml",
+            DataFormat::Ini => "ini",
+        }
+    }
+
+    /// Parse a document of this format.
+    pub fn parse(&self, content: &str) -> Result<Value> {
+        let value = match self {
+            DataFormat::Json => serde_json::from_str(content).context("Failed to parse json")?,
+            DataFormat::Toml => toml::from_str(content).context("Failed to parse toml")?,
+            DataFormat::Yaml => serde_yaml::from_str(content).context("Failed to parse yaml")?,
+            DataFormat::Ini => parse_ini(content)?,
+        };
+
+        Ok(value)
+    }
+
+    /// Serialize a document to this format.
+    pub fn serialize(&self, value: &Value) -> Result<String> {
+        let mut content = match self {
+            DataFormat::Json => {
+                serde_json::to_string_pretty(value).context("Failed to serialize json")?
+            }
+            DataFormat::Toml => {
+                toml::to_string_pretty(value).context("Failed to serialize toml")?
+            }
+            DataFormat::Yaml => serde_yaml::to_string(value).context("Failed to serialize yaml")?,
+            DataFormat::Ini => serialize_ini(value)?,
+        };
+
+        // Files should always end with a newline.
+        if !content.ends_with('\n') {
+            content.push('\n');
+        }
+
+        Ok(content)
+    }
+}
+
+/// Recursively merge `other` into `base`.
+///
+/// Mappings are merged key by key. All other values, including lists, are replaced.
+pub fn deep_merge(base: &mut Value, other: Value) {
+    match (base, other) {
+        (Value::Mapping(base), Value::Mapping(other)) => {
+            for (key, value) in other {
+                match base.get_mut(&key) {
+                    Some(existing) => deep_merge(existing, value),
+                    None => {
+                        base.insert(key, value);
+                    }
+                }
+            }
+        }
+        (base, other) => *base = other,
+    }
+}
+
+/// Parse an INI document.
+///
+/// Keys outside of any section are top-level keys, sections are nested mappings.
+/// All values are read as strings. Comments and empty lines are ignored.
+fn parse_ini(content: &str) -> Result<Value> {
+    let mut document = Mapping::new();
+    let mut section: Option<String> = None;
+
+    for (index, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with(['#', ';']) {
+            continue;
+        }
+
+        if let Some(name) = line
+            .strip_prefix('[')
+            .and_then(|line| line.strip_suffix(']'))
+        {
+            let name = name.trim().to_string();
+            document
+                .entry(Value::String(name.clone()))
+                .or_insert_with(|| Value::Mapping(Mapping::new()));
+            section = Some(name);
+            continue;
+        }
+
+        let Some((key, value)) = line.split_once('=') else {
+            bail!("Failed to parse ini: Invalid line {}: {line}", index + 1);
+        };
+        let key = Value::String(key.trim().to_string());
+        let value = Value::String(value.trim().to_string());
+
+        match &section {
+            Some(name) => {
+                if let Some(Value::Mapping(section)) = document.get_mut(name.as_str()) {
+                    section.insert(key, value);
+                }
+            }
+            None => {
+                document.insert(key, value);
+            }
+        }
+    }
+
+    Ok(Value::Mapping(document))
+}
+
+/// Serialize a document to INI.
+///
+/// Top-level scalars are written first, followed by one section for each top-level mapping.
+/// Lists and deeper nesting can't be represented in INI.
+fn serialize_ini(value: &Value) -> Result<String> {
+    let Value::Mapping(document) = value else {
+        bail!("Failed to serialize ini: The document must be a mapping");
+    };
+
+    let mut globals = Vec::new();
+    let mut sections = Vec::new();
+    for (key, value) in document {
+        let key = ini_scalar(key)?;
+        match value {
+            Value::Mapping(section) => {
+                let mut lines = vec![format!("[{key}]")];
+                for (key, value) in section {
+                    lines.push(format!("{}={}", ini_scalar(key)?, ini_scalar(value)?));
+                }
+                sections.push(lines.join("\n"));
+            }
+            value => globals.push(format!("{key}={}", ini_scalar(value)?)),
+        }
+    }
+
+    let mut blocks = Vec::new();
+    if !globals.is_empty() {
+        blocks.push(globals.join("\n"));
+    }
+    blocks.extend(sections);
+
+    Ok(blocks.join("\n\n"))
+}
+
+/// Convert a scalar to its INI representation.
+fn ini_scalar(value: &Value) -> Result<String> {
+    let value = match value {
+        Value::Null => String::new(),
+        Value::Bool(value) => value.to_string(),
+        Value::Number(value) => value.to_string(),
+        Value::String(value) => value.clone(),
+        _ => bail!("Failed to serialize ini: Only scalars are allowed inside sections"),
+    };
+
+    Ok(value)
+}
@@ -0,0 +1,205 @@
+//! Directories with `fragments: true` don't deploy their files individually.
+//! Instead, all of their files are assembled into a single target file, which allows several
+//! groups to contribute to the same file.
+//!
+//! For example, `groups/base/hosts.d/10-base` and `groups/pc/hosts.d/20-pc` are both assembled
+//! into `/etc/hosts`. Fragments are ordered by their file name across all groups.
+//!
+//! Fragments of json, toml, yaml or ini files are merged, while all other fragments are
+//! concatenated. The assembled file then replaces the first fragment directory, so it's treated
+//! like any other managed file from there on.
+use std::{
+    collections::{BTreeMap, HashSet},
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result, bail};
+use serde_yaml::Value;
+
+use super::{
+    directory::Directory,
+    file::{Entry, File},
+    format::{DataFormat, deep_merge},
+};
+
+/// All fragments of a single target file.
+#[derive(Default)]
+struct Fragments<'a> {
+    /// The first fragment directory that contributes to this file.
+    directory: Option<&'a Directory>,
+    /// All fragments with their file name.
+    files: Vec<(String, &'a File, &'a Directory)>,
+}
+
+/// Assemble all fragment directories of the given host and group directories.
+/// `root` is the target directory.
+pub fn assemble_fragments(root: &Path, directories: &mut [&mut Directory]) -> Result<()> {
+    // First up, collect all fragments by their target path.
+    let mut fragments = BTreeMap::new();
+    for directory in directories.iter() {
+        collect_fragments(root, directory, &mut fragments)?;
+    }
+    if fragments.is_empty() {
+        return Ok(());
+    }
+
+    let mut assembled = BTreeMap::new();
+    for (target, fragments) in fragments {
+        assembled.insert(target.clone(), assemble(&target, fragments)?);
+    }
+
+    // Replace the first fragment directory of each target with the assembled file.
+    // All other fragment directories are removed.
+    let mut placed = HashSet::new();
+    for directory in directories.iter_mut() {
+        replace_fragments(root, directory, &assembled, &mut placed);
+    }
+
+    Ok(())
+}
+
+/// The path of the file the fragments of a directory are assembled into.
+/// A trailing `.d` of the directory's name is removed, e.g. `hosts.d` becomes `hosts`.
+fn target_path(root: &Path, directory: &Directory) -> PathBuf {
+    let path = directory.file_path(root);
+    match path.to_str().and_then(|path| path.strip_suffix(".d")) {
+        Some(stripped) => PathBuf::from(stripped),
+        None => path,
+    }
+}
+
+/// Recursively collect the files of all fragment directories by their target path.
+fn collect_fragments<'a>(
+    root: &Path,
+    directory: &'a Directory,
+    fragments: &mut BTreeMap<PathBuf, Fragments<'a>>,
+) -> Result<()> {
+    for entry in directory.entries.iter() {
+        let Entry::Directory(dir) = entry else {
+            continue;
+        };
+
+        if !dir.config.fragments {
+            collect_fragments(root, dir, fragments)?;
+            continue;
+        }
+
+        let target = fragments.entry(target_path(root, dir)).or_default();
+        target.directory.get_or_insert(dir);
+        for entry in dir.entries.iter() {
+            match entry {
+                Entry::File(file) => {
+                    let name = file
+                        .relative_path
+                        .file_name()
+                        .map(|name| name.to_string_lossy().to_string())
+                        .unwrap_or_default();
+                    target.files.push((name, file, dir));
+                }
+                Entry::Directory(sub_dir) => bail!(
+                    "Fragment directories must not contain directories. Found {:?}",
+                    sub_dir.relative_path
+                ),
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Assemble all fragments of a target into a single file.
+///
+/// Owner, group, mode and validation are taken from the first fragment, falling back to the
+/// fragment directory's config. The `on_change` hooks of all fragments and their directories
+/// are combined.
+fn assemble(target: &Path, mut fragments: Fragments) -> Result<File> {
+    // The sort is stable, so fragments with the same name keep the order of their groups.
+    fragments.files.sort_by(|a, b| a.0.cmp(&b.0));
+    let directory = fragments
+        .directory
+        .expect("Fragments always have a directory");
+
+    let content = match DataFormat::from_path(target) {
+        Some(format) => {
+            let mut merged = Value::Null;
+            for (_, file, _) in fragments.files.iter() {
+                let value = format
+                    .parse(&file.content)
+                    .context(format!("Invalid fragment {:?}", file.relative_path))?;
+                deep_merge(&mut merged, value);
+            }
+            format.serialize(&merged)?
+        }
+        None => {
+            let mut content = String::new();
+            for (_, file, _) in fragments.files.iter() {
+                content.push_str(file.content.trim_end());
+                content.push('\n');
+            }
+            content
+        }
+    };
+
+    let mut file = match fragments.files.first() {
+        Some((_, file, _)) => (*file).clone(),
+        None => File {
+            relative_path: directory.relative_path.clone(),
+            config: Default::default(),
+            content: String::new(),
+            mode: 0o644,
+        },
+    };
+    file.relative_path = directory.relative_path.clone();
+    file.content = content;
+    file.config.override_path(target.to_path_buf());
+    file.config.rename = None;
+    if file.config.owner.is_none() {
+        file.config.owner = directory.config.owner.clone();
+    }
+    if file.config.group.is_none() {
+        file.config.group = directory.config.group.clone();
+    }
+
+    let mut hooks = Vec::new();
+    for (_, fragment, dir) in fragments.files.iter() {
+        for hook in dir
+            .config
+            .on_change
+            .iter()
+            .chain(fragment.config.on_change.iter())
+        {
+            if !hooks.contains(hook) {
+                hooks.push(hook.clone());
+            }
+        }
+    }
+    file.config.on_change = hooks;
+
+    Ok(file)
+}
+
+/// Recursively replace the fragment directories with their assembled files.
+fn replace_fragments(
+    root: &Path,
+    directory: &mut Directory,
+    assembled: &BTreeMap<PathBuf, File>,
+    placed: &mut HashSet<PathBuf>,
+) {
+    let entries = std::mem::take(&mut directory.entries);
+    for entry in entries {
+        match entry {
+            Entry::Directory(dir) if dir.config.fragments => {
+                let target = target_path(root, &dir);
+                if placed.insert(target.clone()) {
+                    let file = assembled[&target].clone();
+                    directory.entries.push(Entry::File(file));
+                }
+            }
+            Entry::Directory(mut dir) => {
+                replace_fragments(root, &mut dir, assembled, placed);
+                directory.entries.push(Entry::Directory(dir));
+            }
+            entry => directory.entries.push(entry),
+        }
+    }
+}
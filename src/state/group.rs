@@ -1,13 +1,19 @@
 use std::{
-    collections::{HashMap, HashSet},
+    collections::{BTreeMap, HashMap, HashSet},
     path::{Path, PathBuf},
 };
 
 use anyhow::{Result, bail};
 use serde::{Deserialize, Serialize};
+use serde_yaml::Mapping;
 
 use super::{directory::*, file::read_entry};
-use crate::{config::helper::read_yaml, error::Error, handlers::packages::PackageManager};
+use crate::{
+    config::helper::read_yaml,
+    error::Error,
+    handlers::packages::PackageManager,
+    templating::{TemplateContext, variables::VARS_DIR},
+};
 
 #[derive(Clone, Debug, Default, Serialize, Deserialize)]
 pub struct Group {
@@ -32,6 +38,20 @@ pub struct GroupConfig {
     /// Packages that should always be installed for this group.
     #[serde(default)]
     pub packages: HashMap<PackageManager, HashSet<String>>,
+    /// Paths that must not exist on the system.
+    /// Relative paths are relative to the target directory.
+    #[serde(default)]
+    pub absent: Vec<PathBuf>,
+    /// Keys that should be set or removed in key/value files, sorted by path.
+    /// Relative paths are relative to the target directory.
+    #[serde(default)]
+    pub settings: BTreeMap<PathBuf, Mapping>,
+    /// Commands that're run before anything is changed on the system during a deploy.
+    #[serde(default)]
+    pub pre_deploy: Vec<String>,
+    /// Commands that're run after all changes have been deployed.
+    #[serde(default)]
+    pub post_deploy: Vec<String>,
 }
 
 #[derive(Clone, Debug, Default, Deserialize, Serialize)]
@@ -42,8 +62,13 @@ pub struct GroupDefaults {
     pub directory_mode: Option<u32>,
 }
 
-pub fn read_group(root: &Path, name: &str, template_vars: &serde_yaml::Value) -> Result<Group> {
-    let group_dir = root.join("groups").join(name);
+/// Read the directory of the group `name` inside the bois directory of the `context`.
+pub fn read_group(
+    context: &TemplateContext,
+    name: &str,
+    template_vars: &serde_yaml::Value,
+) -> Result<Group> {
+    let group_dir = context.bois_dir.join("groups").join(name);
 
     if !group_dir.exists() {
         eprintln!("Couldn't find config directory for gruop {group_dir:?}. Aborting.");
@@ -69,8 +94,10 @@ pub fn read_group(root: &Path, name: &str, template_vars: &serde_yaml::Value) ->
     for entry in entries {
         let entry = entry.map_err(|err| Error::IoPath(group_dir.clone(), "reading entry", err))?;
 
-        // Don't include the group configuration file. It's already handled above
-        if entry.file_name() == "group.yml" {
+        // Don't include the group or variable configuration files.
+        if ["group.yml", "group.yaml", "vars.yml", "vars.yaml", VARS_DIR]
+            .contains(&entry.file_name().to_str().unwrap())
+        {
             continue;
         }
 
@@ -81,6 +108,7 @@ pub fn read_group(root: &Path, name: &str, template_vars: &serde_yaml::Value) ->
             &mut directory,
             config.target_directory.clone(),
             template_vars,
+            Some(context),
         )?;
     }
 
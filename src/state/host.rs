@@ -1,18 +1,23 @@
 use std::{
-    collections::{HashMap, HashSet},
+    collections::{BTreeMap, HashMap, HashSet},
     path::{Path, PathBuf},
 };
 
-use anyhow::{Result, bail};
+use anyhow::{Context, Result, bail};
+use log::info;
 use serde::{Deserialize, Serialize};
-use serde_yaml::Value;
+use serde_yaml::{Mapping, Value};
 
 use super::{directory::*, file::read_entry, group::Group};
 use crate::{
     config::helper::read_yaml,
     error::Error,
     handlers::packages::PackageManager,
-    templating::variables::get_host_vars,
+    templating::{
+        TemplateContext,
+        evaluate_condition,
+        variables::{VARS_DIR, VariableLayer, host_variable_layers, insert_groups, merge_layers},
+    },
 };
 
 /// A Host is related to a
@@ -20,10 +25,17 @@ use crate::{
 pub struct Host {
     /// The top-level configuration file for this host.
     pub config: HostConfig,
-    /// All variables that're available for templating to the host files and all groups.
+    /// All variables that're available for templating to the host files.
+    /// Group files use their own variables instead, see
+    /// [group_variables](crate::templating::variables::group_variables).
     pub variables: Value,
+    /// The layers the host's variables have been merged from, see [host_variable_layers].
+    /// Groups insert their own variables into these layers.
+    pub variable_layers: Vec<VariableLayer>,
     /// The content of this group's directory.
     pub directory: Directory,
+    /// The names of all groups that're enabled for this host, after evaluating their conditions.
+    pub group_names: Vec<String>,
     /// Will contain all groups that have been specified as dependencies.
     pub groups: Vec<Group>,
 }
@@ -40,10 +52,47 @@ pub struct HostConfig {
     pub file_defaults: HostDefaults,
     /// Groups that're required by this host.
     #[serde(default)]
-    pub groups: Vec<String>,
+    pub groups: Vec<GroupEntry>,
     /// Packages that should always be installed for this host.
     #[serde(default)]
     pub packages: HashMap<PackageManager, HashSet<String>>,
+    /// Paths that must not exist on the system.
+    /// Relative paths are relative to the target directory.
+    #[serde(default)]
+    pub absent: Vec<PathBuf>,
+    /// Keys that should be set or removed in key/value files, sorted by path.
+    /// Relative paths are relative to the target directory.
+    #[serde(default)]
+    pub settings: BTreeMap<PathBuf, Mapping>,
+    /// Commands that're run before anything is changed on the system during a deploy.
+    #[serde(default)]
+    pub pre_deploy: Vec<String>,
+    /// Commands that're run after all changes have been deployed.
+    #[serde(default)]
+    pub post_deploy: Vec<String>,
+}
+
+/// A group that's enabled for a host.
+/// It's either just the name of the group, or a group with a condition.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(untagged)]
+pub enum GroupEntry {
+    Name(String),
+    Conditional {
+        name: String,
+        /// The group is only enabled if this condition is met.
+        /// It's evaluated with the host's variables.
+        when: String,
+    },
+}
+
+impl GroupEntry {
+    pub fn name(&self) -> &str {
+        match self {
+            GroupEntry::Name(name) => name,
+            GroupEntry::Conditional { name, .. } => name,
+        }
+    }
 }
 
 #[derive(Clone, Debug, Default, Deserialize, Serialize)]
@@ -54,7 +103,13 @@ pub struct HostDefaults {
     pub directory_mode: Option<u32>,
 }
 
-pub fn read_host(root: &Path, hostname: &str) -> Result<Host> {
+/// Read the host directory of `hostname` inside the bois directory of the `context`.
+pub fn read_host(
+    context: &TemplateContext,
+    hostname: &str,
+    overrides: &[VariableLayer],
+) -> Result<Host> {
+    let root = &context.bois_dir;
     let host_dir = root.join("hosts").join(hostname);
 
     if !host_dir.exists() {
@@ -66,7 +121,12 @@ pub fn read_host(root: &Path, hostname: &str) -> Result<Host> {
     let config = read_yaml::<HostConfig>(&host_dir, "host")?;
 
     // Load a template file if it exists and pre-seed some default templating values.
-    let templating_vars = get_host_vars(&host_dir, hostname, &config)?;
+    let variable_layers = host_variable_layers(root, hostname, overrides)?;
+    let mut templating_vars = merge_layers(&variable_layers);
+
+    // Determine the enabled groups, which may depend on the host's variables.
+    let group_names = resolve_groups(&config.groups, &templating_vars, Some(context))?;
+    insert_groups(&mut templating_vars, &group_names);
 
     // Now we recursively read all files in the host directory
     // First, read the directory entries.
@@ -78,8 +138,8 @@ pub fn read_host(root: &Path, hostname: &str) -> Result<Host> {
         let entry =
             entry.map_err(|err| Error::IoPath(host_dir.clone(), "reading host dir entry", err))?;
 
-        // Don't include the host or variable configuration file. It's already handled above
-        if ["host.yml", "host.yaml", "vars.yml", "vars.yaml"]
+        // Don't include the host or variable configuration files. They're already handled above
+        if ["host.yml", "host.yaml", "vars.yml", "vars.yaml", VARS_DIR]
             .contains(&entry.file_name().to_str().unwrap())
         {
             continue;
@@ -92,13 +152,39 @@ pub fn read_host(root: &Path, hostname: &str) -> Result<Host> {
             &mut files,
             config.target_directory.clone(),
             &templating_vars,
+            Some(context),
         )?;
     }
 
     Ok(Host {
         config,
         variables: templating_vars,
+        variable_layers,
         directory: files,
+        group_names,
         groups: Vec::new(),
     })
 }
+
+/// Get the names of all groups whose conditions are met.
+pub fn resolve_groups(
+    groups: &[GroupEntry],
+    variables: &Value,
+    context: Option<&TemplateContext>,
+) -> Result<Vec<String>> {
+    let mut names = Vec::new();
+    for group in groups {
+        if let GroupEntry::Conditional { name, when } = group {
+            let enabled = evaluate_condition(when, variables, context)
+                .context(format!("Error for condition of group {name}"))?;
+            if !enabled {
+                info!("Skipping group {name} due to its condition");
+                continue;
+            }
+        }
+
+        names.push(group.name().to_string());
+    }
+
+    Ok(names)
+}
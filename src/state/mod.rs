@@ -1,29 +1,36 @@
 use std::{
-    collections::{HashMap, HashSet},
-    fs::File,
-    io::{BufReader, Write},
+    collections::{BTreeMap, HashMap, HashSet},
+    path::{Path, PathBuf},
 };
 
-use anyhow::{Result, bail};
-use log::{info, warn};
+use anyhow::{Context, Result, bail};
+use log::warn;
 use serde::{Deserialize, Serialize};
 
 use crate::{
-    config::bois::Configuration,
-    error::Error,
+    changeset::settings::Settings,
+    config::{bois::Configuration, file::FileState, helper::expand_home},
     handlers::packages::{PackageManager, pacman::get_packages_for_group},
     system_state::SystemState,
+    templating::{TemplateContext, variables::group_variables},
 };
 
 pub mod directory;
 pub mod file;
 pub mod file_parser;
+pub mod format;
+pub mod fragments;
 pub mod group;
 pub mod host;
+pub mod patch;
 
 use self::{
+    directory::Directory,
+    file::Entry,
+    fragments::assemble_fragments,
     group::read_group,
     host::{Host, read_host},
+    patch::{apply_patches, check_conflicts},
 };
 
 /// This struct all configuration that's applicable for this machine.
@@ -39,14 +46,25 @@ pub struct State {
     pub host: Host,
     /// All variables that're available to all groups during templating.
     pub variables: HashMap<String, String>,
-    /// The current configuration
-    /// We have to save this as well, as we would otherwise loose information about
-    /// previous runs, if the config changed in the meantime such as, for instance, a different
-    /// root dir or new hostname.
+    /// The configuration this state has been built with.
     pub configuration: Configuration,
 
     /// The compiled list of all packages that should be installed for this current configuration.
     pub packages: HashMap<PackageManager, HashSet<String>>,
+
+    /// All `pre_deploy` hooks of the host and its groups, in that order.
+    pub pre_deploy_hooks: Vec<String>,
+    /// All `post_deploy` hooks of the host and its groups, in that order.
+    pub post_deploy_hooks: Vec<String>,
+
+    /// All keys that should be set or removed in key/value files, sorted by target path.
+    /// Settings are applied in order, so the host's settings are last and take precedence.
+    pub settings: BTreeMap<PathBuf, Vec<Settings>>,
+
+    /// The target paths that must not exist on the system.
+    /// This includes the `absent` lists of the host and its groups, as well as all files with
+    /// `state: absent`.
+    pub absent_paths: Vec<PathBuf>,
 }
 
 impl State {
@@ -62,22 +80,64 @@ impl State {
 
         // Read the initial group for this host.
         // This specifieds all other dependencies.
-        let mut host = read_host(&configuration.bois_dir, &configuration.name)?;
+        let context = TemplateContext::new(configuration);
+        let mut host = read_host(&context, &configuration.name, &configuration.overrides)?;
 
         // Go through all dependencies and load them as well.
-        for group_name in &host.config.groups {
-            let group = read_group(&configuration.bois_dir, group_name, &host.variables)?;
+        // Each group's files are templated with the group's own variables.
+        for group_name in &host.group_names {
+            let variables = group_variables(
+                &configuration.bois_dir,
+                group_name,
+                &host.variable_layers,
+                &host.group_names,
+            )?;
+            let group = read_group(&context, group_name, &variables)?;
             host.groups.push(group);
         }
 
+        // Replace all patches with the patched original files.
+        // All conflicts are collected first, so they can be reported at once.
+        let mut conflicts = Vec::new();
+        apply_patches(
+            &configuration.target_dir,
+            &mut host.directory,
+            &mut conflicts,
+        )?;
+        for group in host.groups.iter_mut() {
+            apply_patches(
+                &configuration.target_dir,
+                &mut group.directory,
+                &mut conflicts,
+            )?;
+        }
+        check_conflicts(&conflicts)?;
+
+        // Assemble all fragments into their target files.
+        let mut directories: Vec<&mut Directory> = std::iter::once(&mut host.directory)
+            .chain(host.groups.iter_mut().map(|group| &mut group.directory))
+            .collect();
+        assemble_fragments(&configuration.target_dir, &mut directories)?;
+
+        // Paths may be templated, so collisions can only be detected once everything is read.
+        check_collisions(&configuration.target_dir, &configuration.name, &host)?;
+
         let mut state = State {
             host,
             variables: HashMap::new(),
             configuration: configuration.clone(),
             packages: HashMap::new(),
+            pre_deploy_hooks: Vec::new(),
+            post_deploy_hooks: Vec::new(),
+            absent_paths: Vec::new(),
+            settings: BTreeMap::new(),
         };
 
         state.load_packages(system_state)?;
+        state.load_deploy_hooks();
+        state.load_absent_paths();
+        state.check_absent_conflicts()?;
+        state.load_settings()?;
 
         Ok(state)
     }
@@ -138,59 +198,288 @@ impl State {
         Ok(())
     }
 
-    /// Try to read the state of a previous deployment.
-    /// This state will be used to determine:
-    /// - Any changes on the system's files since the last deployment
-    /// - Cleanup work that might need to be done for the new desired state.
+    /// Collect the deploy hooks of the host and all of its groups.
+    /// Hooks that're requested multiple times are only run once.
+    fn load_deploy_hooks(&mut self) {
+        let configs =
+            std::iter::once((&self.host.config.pre_deploy, &self.host.config.post_deploy)).chain(
+                self.host
+                    .groups
+                    .iter()
+                    .map(|group| (&group.config.pre_deploy, &group.config.post_deploy)),
+            );
+
+        for (pre_deploy, post_deploy) in configs {
+            for hook in pre_deploy {
+                if !self.pre_deploy_hooks.contains(hook) {
+                    self.pre_deploy_hooks.push(hook.clone());
+                }
+            }
+            for hook in post_deploy {
+                if !self.post_deploy_hooks.contains(hook) {
+                    self.post_deploy_hooks.push(hook.clone());
+                }
+            }
+        }
+    }
+
+    /// Collect all paths that must not exist on the system.
     ///
-    /// Will return a Ok(None), if no previous state could be found.
-    pub fn read_previous(config: &Configuration) -> Result<Option<Self>> {
-        // Get the path for the deployed state.
-        let path = config.cache_dir.join("deployed_state.yml");
-        info!("Looking for previous state file at {path:?}");
-
-        // Return None if we cannot find any file.
-        if !path.exists() || !path.is_file() {
-            info!("No state file found. Use default config.");
-            return Ok(None);
-        };
+    /// Relative paths in the `absent` lists are relative to the target directory of the
+    /// respective host or group.
+    fn load_absent_paths(&mut self) {
+        let root = &self.configuration.target_dir;
+        let configs =
+            std::iter::once((&self.host.config.target_directory, &self.host.config.absent)).chain(
+                self.host
+                    .groups
+                    .iter()
+                    .map(|group| (&group.config.target_directory, &group.config.absent)),
+            );
+
+        let mut paths = Vec::new();
+        for (target_directory, absent) in configs {
+            for path in absent {
+                paths.push(resolve_target_path(root, target_directory, path));
+            }
+        }
 
-        info!("Found previous deployed state at: {path:?}");
+        add_absent_files(root, &self.host.directory, &mut paths);
+        for group in self.host.groups.iter() {
+            add_absent_files(root, &group.directory, &mut paths);
+        }
 
-        // Open the file in read-only mode with buffer.
-        let file = File::open(&path)
-            .map_err(|err| Error::IoPath(path.clone(), "opening config file.", err))?;
-        let reader = BufReader::new(file);
+        for path in paths {
+            if !self.absent_paths.contains(&path) {
+                self.absent_paths.push(path);
+            }
+        }
+    }
 
-        // Read and deserialize the config file.
-        let state =
-            serde_yaml::from_reader(reader).map_err(|err| Error::Deserialization(path, err))?;
+    /// Make sure that no path is deployed while it, or one of its parents, must be absent.
+    fn check_absent_conflicts(&self) -> Result<()> {
+        let root = &self.configuration.target_dir;
+        let mut deployed = HashSet::new();
+        add_managed_paths(root, &self.host.directory, &mut deployed);
+        for group in self.host.groups.iter() {
+            add_managed_paths(root, &group.directory, &mut deployed);
+        }
 
-        Ok(state)
+        let mut conflicts: Vec<String> = deployed
+            .iter()
+            .filter_map(|path| {
+                let absent = self
+                    .absent_paths
+                    .iter()
+                    .find(|absent| path.starts_with(absent))?;
+                Some(format!(
+                    "{path:?} is deployed, but {absent:?} must be absent"
+                ))
+            })
+            .collect();
+
+        if !conflicts.is_empty() {
+            conflicts.sort();
+            bail!(
+                "Found paths that are both deployed and absent:\n{}",
+                conflicts.join("\n")
+            );
+        }
+
+        Ok(())
     }
 
-    /// Save the current desired state as a file. \
-    /// Read the `self.read` docs on why we need this file at all.
-    pub fn save(&self) -> Result<(), Error> {
-        let path = self.configuration.cache_dir.join("deployed_state.yml");
-        info!("Looking for previous state file at {path:?}");
-
-        // Serialize the configuration file and write it to disk
-        let content = match serde_yaml::to_string(self) {
-            Ok(content) => content,
-            Err(error) => {
-                return Err(Error::Generic(format!(
-                    "Configuration file serialization failed:\n{error}"
-                )));
+    /// Collect the settings of all groups and the host, in that order.
+    fn load_settings(&mut self) -> Result<()> {
+        let root = &self.configuration.target_dir;
+        let configs = self
+            .host
+            .groups
+            .iter()
+            .map(|group| (&group.config.target_directory, &group.config.settings))
+            .chain(std::iter::once((
+                &self.host.config.target_directory,
+                &self.host.config.settings,
+            )));
+
+        for (target_directory, settings) in configs {
+            for (path, mapping) in settings {
+                let path = resolve_target_path(root, target_directory, path);
+                let settings = Settings::from_yaml(mapping)
+                    .context(format!("Invalid settings for {path:?}"))?;
+                self.settings.entry(path).or_default().push(settings);
             }
-        };
-
-        // Write the serialized content to the file.
-        let mut file = File::create(&path)
-            .map_err(|err| Error::IoPath(path.clone(), "creating state file", err))?;
-        file.write_all(content.as_bytes())
-            .map_err(|err| Error::IoPath(path, "writing state file", err))?;
+        }
 
         Ok(())
     }
+
+    /// Get the target paths of all files and directories that're managed by this state.
+    ///
+    /// Paths that must be absent and files with settings are managed as well, so they're never
+    /// touched by a purge.
+    pub fn managed_paths(&self) -> HashSet<PathBuf> {
+        let mut paths = HashSet::new();
+        let root = &self.configuration.target_dir;
+
+        add_managed_paths(root, &self.host.directory, &mut paths);
+        for group in self.host.groups.iter() {
+            add_managed_paths(root, &group.directory, &mut paths);
+        }
+        paths.extend(self.absent_paths.iter().cloned());
+        paths.extend(self.settings.keys().cloned());
+
+        paths
+    }
+}
+
+/// Make sure that no two files are deployed to the same path and that no file is deployed to the
+/// path of a directory. Directories may be shared by several groups and files may be shared by
+/// several managed blocks.
+/// Files with settings must not be deployed by any entry either.
+fn check_collisions(root: &Path, host_name: &str, host: &Host) -> Result<()> {
+    let mut files = HashMap::new();
+    let mut directories = HashMap::new();
+    let mut collisions = Vec::new();
+
+    let sources = std::iter::once((Path::new("hosts").join(host_name), &host.directory)).chain(
+        host.groups
+            .iter()
+            .map(|group| (Path::new("groups").join(&group.name), &group.directory)),
+    );
+    for (origin, directory) in sources {
+        collect_targets(
+            root,
+            &origin,
+            directory,
+            &mut files,
+            &mut directories,
+            &mut collisions,
+        );
+    }
+
+    for (path, (origin, _)) in files.iter() {
+        if let Some(directory_origin) = directories.get(path) {
+            collisions.push(format!(
+                "{path:?} is deployed as a file by {origin:?} and as a directory by \
+                {directory_origin:?}"
+            ));
+        }
+    }
+
+    // Settings edit a file in place, so the file can't be deployed as a whole as well.
+    let settings = std::iter::once((Path::new("hosts").join(host_name), &host.config))
+        .map(|(origin, config)| {
+            (
+                origin.join("host.yml"),
+                &config.target_directory,
+                &config.settings,
+            )
+        })
+        .chain(host.groups.iter().map(|group| {
+            (
+                Path::new("groups").join(&group.name).join("group.yml"),
+                &group.config.target_directory,
+                &group.config.settings,
+            )
+        }));
+    for (origin, target_directory, settings) in settings {
+        for path in settings.keys() {
+            let path = resolve_target_path(root, target_directory, path);
+            if let Some((file_origin, _)) = files.get(&path) {
+                collisions.push(format!(
+                    "{path:?} is deployed by {file_origin:?} and has settings in {origin:?}"
+                ));
+            } else if let Some(directory_origin) = directories.get(&path) {
+                collisions.push(format!(
+                    "{path:?} is deployed as a directory by {directory_origin:?} and has settings \
+                     in {origin:?}"
+                ));
+            }
+        }
+    }
+
+    if !collisions.is_empty() {
+        collisions.sort();
+        bail!("Found colliding target paths:\n{}", collisions.join("\n"));
+    }
+
+    Ok(())
+}
+
+/// Recursively collect the target paths of all entries by their source path.
+/// Files are stored together with whether they only manage a block.
+fn collect_targets(
+    root: &Path,
+    origin: &Path,
+    directory: &Directory,
+    files: &mut HashMap<PathBuf, (PathBuf, bool)>,
+    directories: &mut HashMap<PathBuf, PathBuf>,
+    collisions: &mut Vec<String>,
+) {
+    for entry in directory.entries.iter() {
+        match entry {
+            Entry::File(file) => {
+                let source = origin.join(&file.relative_path);
+                let block = file.block_markers().is_some();
+                // Several files may manage different blocks inside the same file.
+                if let Some((existing, existing_block)) =
+                    files.insert(file.file_path(root), (source.clone(), block))
+                    && !(block && existing_block)
+                {
+                    collisions.push(format!(
+                        "{:?} is deployed by both {existing:?} and {source:?}",
+                        file.file_path(root)
+                    ));
+                }
+            }
+            Entry::Directory(dir) => {
+                directories.insert(dir.file_path(root), origin.join(&dir.relative_path));
+                collect_targets(root, origin, dir, files, directories, collisions);
+            }
+        }
+    }
+}
+
+/// Resolve a path from a host or group config.
+/// Relative paths are relative to the target directory of that host or group.
+fn resolve_target_path(root: &Path, target_directory: &Option<PathBuf>, path: &Path) -> PathBuf {
+    let base = match target_directory {
+        Some(directory) => root.join(expand_home(directory)),
+        None => root.to_path_buf(),
+    };
+
+    base.join(expand_home(path))
+}
+
+/// Recursively add the target paths of all files with `state: absent` in a directory.
+fn add_absent_files(root: &Path, directory: &Directory, paths: &mut Vec<PathBuf>) {
+    for entry in directory.entries.iter() {
+        match entry {
+            Entry::File(file) => {
+                if file.config.state == FileState::Absent {
+                    paths.push(file.file_path(root));
+                }
+            }
+            Entry::Directory(dir) => add_absent_files(root, dir, paths),
+        }
+    }
+}
+
+/// Recursively add the target paths of all deployed entries in a directory.
+/// Files with `state: absent` aren't deployed, so they're skipped.
+fn add_managed_paths(root: &Path, directory: &Directory, paths: &mut HashSet<PathBuf>) {
+    for entry in directory.entries.iter() {
+        match entry {
+            Entry::File(file) => {
+                if file.config.state != FileState::Absent {
+                    paths.insert(file.file_path(root));
+                }
+            }
+            Entry::Directory(dir) => {
+                paths.insert(dir.file_path(root));
+                add_managed_paths(root, dir, paths);
+            }
+        }
+    }
 }
@@ -0,0 +1,84 @@
+//! Files whose name ends with `.patch` are unified diffs.
+//! Instead of deploying the diff itself, it's applied to the original file of the package that
+//! owns the target path, and the result is deployed.
+//!
+//! That way, only the actual changes to upstream configuration files need to be kept in the
+//! bois directory.
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result, bail};
+use diffy::{Patch, apply};
+use log::info;
+
+use super::{directory::Directory, file::Entry};
+use crate::{config::helper::expand_home, handlers::packages::pacman::original_file};
+
+/// The file extension of patch files.
+pub const PATCH_EXTENSION: &str = "patch";
+
+/// Apply a unified diff to the original content of a file.
+///
+/// Fails with a conflict if the patch doesn't apply cleanly, e.g. because the original file
+/// changed after a package update.
+pub fn apply_patch(original: &str, patch: &str) -> Result<String> {
+    let patch = Patch::from_str(patch).context("Couldn't parse patch")?;
+
+    apply(original, &patch).context("Patch conflict: Patch doesn't apply to the original file")
+}
+
+/// Recursively apply all patch files of a directory to their originals.
+/// The content of every patch file is replaced by the patched original.
+///
+/// All patch conflicts are collected in `conflicts`, so they can be reported at once.
+pub fn apply_patches(
+    root: &Path,
+    directory: &mut Directory,
+    conflicts: &mut Vec<(PathBuf, String)>,
+) -> Result<()> {
+    for entry in directory.entries.iter_mut() {
+        let file = match entry {
+            Entry::File(file) if file.is_patch() => file,
+            Entry::File(_) => continue,
+            Entry::Directory(dir) => {
+                apply_patches(root, dir, conflicts)?;
+                continue;
+            }
+        };
+
+        let path = file.file_path(root);
+        info!("Applying patch {:?} to {path:?}", file.relative_path);
+        let original = match &file.config.original {
+            Some(original) => {
+                let original = expand_home(original);
+                std::fs::read_to_string(&original)
+                    .context(format!("Couldn't read original {original:?} for {path:?}"))?
+            }
+            None => original_file(&path)?,
+        };
+
+        match apply_patch(&original, &file.content) {
+            Ok(content) => file.content = content,
+            Err(err) => conflicts.push((path, format!("{err:#}"))),
+        }
+    }
+
+    Ok(())
+}
+
+/// Bail with a list of all patch conflicts, if there are any.
+pub fn check_conflicts(conflicts: &[(PathBuf, String)]) -> Result<()> {
+    if conflicts.is_empty() {
+        return Ok(());
+    }
+
+    let conflicts = conflicts
+        .iter()
+        .map(|(path, err)| format!("{path:?}: {err}"))
+        .collect::<Vec<_>>();
+    bail!(
+        "Found {} patch conflicts. The original files probably changed, please update the \
+         patches.\n{}",
+        conflicts.len(),
+        conflicts.join("\n")
+    );
+}
@@ -0,0 +1,238 @@
+//! This module gathers facts about the current system, which are exposed to templates via the
+//! `facts` variable.
+//!
+//! All facts are read from local sources, such as `/proc`, `/sys` and `/etc`.
+//! Facts whose source isn't available are left empty instead of failing, as not every system
+//! provides all of them (e.g. containers).
+use std::{
+    collections::BTreeMap,
+    fs::{read_dir, read_to_string},
+    path::Path,
+};
+
+use serde::Serialize;
+
+#[derive(Debug, Default, Serialize)]
+pub struct Facts {
+    /// All entries of `/etc/os-release` with lowercase keys, e.g. `id` or `version_id`.
+    pub os: BTreeMap<String, String>,
+    pub kernel: KernelFacts,
+    pub cpu: CpuFacts,
+    pub memory: MemoryFacts,
+    /// All network interfaces, sorted by name.
+    pub network: Vec<NetworkInterface>,
+    /// Whether the system has at least one battery.
+    pub has_battery: bool,
+    /// The vendors of all GPUs, e.g. `amd`, `intel` or `nvidia`.
+    pub gpus: Vec<String>,
+    /// The content of `/etc/machine-id`.
+    pub machine_id: Option<String>,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct KernelFacts {
+    /// The kernel release, e.g. `6.9.1-arch1-1`.
+    pub release: Option<String>,
+    /// The architecture bois has been compiled for, e.g. `x86_64`.
+    pub arch: String,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct CpuFacts {
+    /// The number of logical cores.
+    pub count: usize,
+    /// The vendor of the CPU, either `amd`, `intel` or the raw vendor id.
+    pub vendor: Option<String>,
+    pub model: Option<String>,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct MemoryFacts {
+    /// The total memory in megabytes.
+    pub total_mb: Option<u64>,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct NetworkInterface {
+    pub name: String,
+    pub mac: Option<String>,
+    pub wireless: bool,
+}
+
+/// Gather all facts of the current system.
+pub fn gather_facts() -> Facts {
+    gather_facts_from(Path::new("/"))
+}
+
+/// Gather all facts from the system that's mounted at `root`.
+pub fn gather_facts_from(root: &Path) -> Facts {
+    Facts {
+        os: os_release(root),
+        kernel: KernelFacts {
+            release: read_trimmed(&root.join("proc/sys/kernel/osrelease")),
+            arch: std::env::consts::ARCH.to_string(),
+        },
+        cpu: cpu(root),
+        memory: memory(root),
+        network: network(root),
+        has_battery: has_battery(root),
+        gpus: gpus(root),
+        machine_id: read_trimmed(&root.join("etc/machine-id")),
+    }
+}
+
+/// Read a file and trim it. Returns `None` if it doesn't exist or is empty.
+fn read_trimmed(path: &Path) -> Option<String> {
+    let content = read_to_string(path).ok()?;
+    let content = content.trim();
+    if content.is_empty() {
+        return None;
+    }
+
+    Some(content.to_string())
+}
+
+/// Get the names of all entries of a directory, sorted by name.
+fn entry_names(path: &Path) -> Vec<String> {
+    let Ok(entries) = read_dir(path) else {
+        return Vec::new();
+    };
+
+    let mut names: Vec<String> = entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.file_name().to_string_lossy().to_string())
+        .collect();
+    names.sort();
+
+    names
+}
+
+/// Parse `/etc/os-release`, which consists of `KEY=value` lines with optionally quoted values.
+fn os_release(root: &Path) -> BTreeMap<String, String> {
+    let mut os = BTreeMap::new();
+    let Ok(content) = read_to_string(root.join("etc/os-release")) else {
+        return os;
+    };
+
+    for line in content.lines() {
+        let line = line.trim();
+        if line.starts_with('#') {
+            continue;
+        }
+        let Some((key, value)) = line.split_once('=') else {
+            continue;
+        };
+        let value = value.trim().trim_matches(['"', '\'']);
+        os.insert(key.trim().to_lowercase(), value.to_string());
+    }
+
+    os
+}
+
+/// Read the CPU count, vendor and model from `/proc/cpuinfo`.
+fn cpu(root: &Path) -> CpuFacts {
+    let mut facts = CpuFacts::default();
+    let Ok(content) = read_to_string(root.join("proc/cpuinfo")) else {
+        return facts;
+    };
+
+    for line in content.lines() {
+        let Some((key, value)) = line.split_once(':') else {
+            continue;
+        };
+        let value = value.trim();
+        match key.trim() {
+            "processor" => facts.count += 1,
+            "vendor_id" if facts.vendor.is_none() => {
+                facts.vendor = Some(match value {
+                    "AuthenticAMD" => "amd".to_string(),
+                    "GenuineIntel" => "intel".to_string(),
+                    vendor => vendor.to_string(),
+                });
+            }
+            "model name" if facts.model.is_none() => facts.model = Some(value.to_string()),
+            _ => {}
+        }
+    }
+
+    facts
+}
+
+/// Read the total memory from `/proc/meminfo`.
+fn memory(root: &Path) -> MemoryFacts {
+    let total_kb = read_to_string(root.join("proc/meminfo"))
+        .ok()
+        .and_then(|content| {
+            content.lines().find_map(|line| {
+                let value = line.strip_prefix("MemTotal:")?;
+                value
+                    .trim()
+                    .trim_end_matches("kB")
+                    .trim()
+                    .parse::<u64>()
+                    .ok()
+            })
+        });
+
+    MemoryFacts {
+        total_mb: total_kb.map(|kb| kb / 1024),
+    }
+}
+
+/// Read all network interfaces from `/sys/class/net`.
+fn network(root: &Path) -> Vec<NetworkInterface> {
+    let net_dir = root.join("sys/class/net");
+
+    entry_names(&net_dir)
+        .into_iter()
+        .map(|name| {
+            let interface_dir = net_dir.join(&name);
+            NetworkInterface {
+                mac: read_trimmed(&interface_dir.join("address")),
+                wireless: interface_dir.join("wireless").exists()
+                    || interface_dir.join("phy80211").exists(),
+                name,
+            }
+        })
+        .collect()
+}
+
+/// Check whether any power supply in `/sys/class/power_supply` is a battery.
+fn has_battery(root: &Path) -> bool {
+    let supply_dir = root.join("sys/class/power_supply");
+
+    entry_names(&supply_dir).iter().any(|name| {
+        read_trimmed(&supply_dir.join(name).join("type")).is_some_and(|kind| kind == "Battery")
+    })
+}
+
+/// Read the vendors of all GPUs from `/sys/class/drm`.
+fn gpus(root: &Path) -> Vec<String> {
+    let drm_dir = root.join("sys/class/drm");
+
+    let mut vendors = Vec::new();
+    for name in entry_names(&drm_dir) {
+        // Only look at the cards themselves, not at their connectors like `card0-DP-1`.
+        let is_card = name
+            .strip_prefix("card")
+            .is_some_and(|id| !id.is_empty() && id.chars().all(|char| char.is_ascii_digit()));
+        if !is_card {
+            continue;
+        }
+
+        let Some(vendor_id) = read_trimmed(&drm_dir.join(&name).join("device/vendor")) else {
+            continue;
+        };
+        let vendor = match vendor_id.as_str() {
+            "0x1002" => "amd".to_string(),
+            "0x8086" => "intel".to_string(),
+            "0x10de" => "nvidia".to_string(),
+            other => other.to_string(),
+        };
+        if !vendors.contains(&vendor) {
+            vendors.push(vendor);
+        }
+    }
+
+    vendors
+}
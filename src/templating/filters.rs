@@ -0,0 +1,203 @@
+//! Custom filters and functions for common tasks in configuration files.
+//!
+//! Filters:
+//! - `to_json`, `to_yaml`, `to_toml`, `to_ini` Serialize a value to the respective format.
+//! - `b64encode`, `b64decode` Base64 encoding.
+//! - `sha256` The hex encoded sha256 hash of a string.
+//! - `regex_replace(pattern, replacement)` Replace all matches of a regex.
+//! - `path_join(parts...)` Join paths, also available as a function.
+//! - `basename` The last component of a path.
+//!
+//! Functions:
+//! - `env("VAR", default)` Read an environment variable.
+//! - `read_file("relative/path")` Read a file inside the bois directory.
+//! - `hostname_matches("pattern")` Match the current host against a glob pattern.
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+use base64::{Engine, engine::general_purpose::STANDARD};
+use minijinja::{Environment, Error, ErrorKind, State, Value, value::Rest};
+use regex::Regex;
+use sha2::{Digest, Sha256};
+
+use super::TemplateContext;
+use crate::state::format::DataFormat;
+
+/// Add all custom filters and functions to the environment.
+///
+/// `env` and `read_file` use the `context`, if one is given.
+pub fn add_filters(env: &mut Environment, context: Option<&TemplateContext>) {
+    env.add_filter("to_json", to_json);
+    env.add_filter("to_yaml", to_yaml);
+    env.add_filter("to_toml", to_toml);
+    env.add_filter("to_ini", to_ini);
+    env.add_filter("b64encode", b64encode);
+    env.add_filter("b64decode", b64decode);
+    env.add_filter("sha256", sha256);
+    env.add_filter("regex_replace", regex_replace);
+    env.add_filter("path_join", path_join);
+    env.add_filter("basename", basename);
+
+    env.add_function("path_join", path_join);
+    let envs = context
+        .map(|context| context.envs.clone())
+        .unwrap_or_default();
+    env.add_function("env", move |name: &str, default: Option<String>| {
+        env_var(&envs, name, default)
+    });
+    let bois_dir = context.map(|context| context.bois_dir.clone());
+    env.add_function("read_file", move |path: &str| {
+        read_file(bois_dir.as_deref(), path)
+    });
+    env.add_function("hostname_matches", hostname_matches);
+}
+
+/// Shorthand to create an error for a failed filter or function.
+fn error(message: String) -> Error {
+    Error::new(ErrorKind::InvalidOperation, message)
+}
+
+/// Serialize a value to the given format.
+/// The trailing newline is removed, as the value is usually embedded in a template.
+fn serialize(value: &Value, format: DataFormat) -> Result<String, Error> {
+    let value = serde_yaml::to_value(value)
+        .map_err(|err| error(format!("Failed to convert value: {err}")))?;
+    let content = format
+        .serialize(&value)
+        .map_err(|err| error(format!("{err:#}")))?;
+
+    Ok(content.trim_end_matches('\n').to_string())
+}
+
+/// `{{ value | to_json }}`
+fn to_json(value: Value) -> Result<String, Error> {
+    serialize(&value, DataFormat::Json)
+}
+
+/// `{{ value | to_yaml }}`
+fn to_yaml(value: Value) -> Result<String, Error> {
+    serialize(&value, DataFormat::Yaml)
+}
+
+/// `{{ value | to_toml }}`
+fn to_toml(value: Value) -> Result<String, Error> {
+    serialize(&value, DataFormat::Toml)
+}
+
+/// `{{ value | to_ini }}`
+fn to_ini(value: Value) -> Result<String, Error> {
+    serialize(&value, DataFormat::Ini)
+}
+
+/// `{{ "secret" | b64encode }}`
+fn b64encode(value: &str) -> String {
+    STANDARD.encode(value)
+}
+
+/// `{{ "c2VjcmV0" | b64decode }}`
+fn b64decode(value: &str) -> Result<String, Error> {
+    let bytes = STANDARD
+        .decode(value)
+        .map_err(|err| error(format!("Failed to decode base64: {err}")))?;
+
+    String::from_utf8(bytes).map_err(|err| error(format!("Decoded base64 isn't utf8: {err}")))
+}
+
+/// `{{ "content" | sha256 }}`
+fn sha256(value: &str) -> String {
+    format!("{:x}", Sha256::digest(value.as_bytes()))
+}
+
+/// `{{ "eth0" | regex_replace("^eth", "enp") }}`
+///
+/// The replacement may reference capture groups via `$1` or `${name}`.
+/// Use `${1}` if the group is directly followed by other characters.
+fn regex_replace(value: &str, pattern: &str, replacement: &str) -> Result<String, Error> {
+    let regex =
+        Regex::new(pattern).map_err(|err| error(format!("Invalid regex '{pattern}': {err}")))?;
+
+    Ok(regex.replace_all(value, replacement).to_string())
+}
+
+/// `{{ "/etc" | path_join("nginx", "nginx.conf") }}` or `{{ path_join("/etc", "nginx") }}`
+fn path_join(base: &str, parts: Rest<String>) -> String {
+    let mut path = PathBuf::from(base);
+    for part in parts.iter() {
+        path.push(part);
+    }
+
+    path.to_string_lossy().to_string()
+}
+
+/// `{{ "/etc/nginx/nginx.conf" | basename }}`
+fn basename(path: &str) -> String {
+    Path::new(path)
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default()
+}
+
+/// `{{ env("EDITOR", "vim") }}`
+///
+/// The environment variables from the bois configuration take precedence over the ones of the
+/// current process. Fails if the variable isn't set and there's no default.
+fn env_var(
+    envs: &HashMap<String, String>,
+    name: &str,
+    default: Option<String>,
+) -> Result<String, Error> {
+    let configured = envs.get(name).cloned();
+    if let Some(value) = configured.or_else(|| std::env::var(name).ok()) {
+        return Ok(value);
+    }
+
+    default.ok_or_else(|| error(format!("Environment variable '{name}' isn't set")))
+}
+
+/// `{{ read_file("keys/id_ed25519.pub") }}`
+///
+/// The path is relative to the bois directory. Files outside of it can't be read.
+fn read_file(bois_dir: Option<&Path>, path: &str) -> Result<String, Error> {
+    let Some(bois_dir) = bois_dir else {
+        return Err(error("read_file requires a bois configuration".into()));
+    };
+
+    let bois_dir = bois_dir
+        .canonicalize()
+        .map_err(|err| error(format!("Failed to resolve bois directory: {err}")))?;
+    let file_path = bois_dir
+        .join(path)
+        .canonicalize()
+        .map_err(|err| error(format!("Failed to find file '{path}': {err}")))?;
+
+    // Paths like `../secret` or symlinks could escape the bois directory.
+    if !file_path.starts_with(&bois_dir) {
+        return Err(error(format!(
+            "read_file only allows files inside the bois directory. Got '{path}'"
+        )));
+    }
+
+    std::fs::read_to_string(&file_path)
+        .map_err(|err| error(format!("Failed to read file '{path}': {err}")))
+}
+
+/// `{% if hostname_matches("laptop-*") %}`
+///
+/// Match the current host against a glob pattern, where `*` matches any number of characters
+/// and `?` matches a single character.
+fn hostname_matches(state: &State, pattern: &str) -> Result<bool, Error> {
+    let host = state.lookup("host").unwrap_or_default();
+    let Some(host) = host.as_str() else {
+        return Err(error("The 'host' variable isn't set".into()));
+    };
+
+    let regex = regex::escape(pattern)
+        .replace(r"\*", ".*")
+        .replace(r"\?", ".");
+    let regex = Regex::new(&format!("^{regex}$"))
+        .map_err(|err| error(format!("Invalid pattern '{pattern}': {err}")))?;
+
+    Ok(regex.is_match(host))
+}
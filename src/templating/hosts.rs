@@ -0,0 +1,149 @@
+//! The `hosts` template variable, which exposes the variables of all hosts by their name.
+//!
+//! This allows a host to use values of other hosts, e.g. `{{ hosts.NASboi.ip }}`, without
+//! duplicating them. A host's variables are only read once they're accessed.
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use anyhow::{Result, bail};
+use minijinja::{
+    Environment,
+    Error,
+    ErrorKind,
+    Value,
+    value::{Enumerator, Object, ObjectRepr},
+};
+use serde_yaml::Mapping;
+
+use super::{
+    TemplateContext,
+    variables::{file_variable_layers, merge_layers},
+};
+use crate::{config::helper::read_yaml, error::Error as BoisError, state::host::HostConfig};
+
+/// Add the `hosts` variable to the environment.
+///
+/// The hosts are read from the context's bois directory, so there're none without a context.
+pub fn add_hosts(env: &mut Environment, context: Option<&TemplateContext>) {
+    let Some(context) = context else {
+        return;
+    };
+
+    let hosts = Hosts::with_cache(&context.bois_dir, context.hosts.clone());
+    env.add_global("hosts", Value::from_object(hosts));
+}
+
+/// The already read variables of other hosts.
+///
+/// The cache is shared by all clones, so a [TemplateContext] only reads every host once, even
+/// though each template gets its own environment.
+#[derive(Clone, Debug, Default)]
+pub struct HostsCache(Arc<Mutex<BTreeMap<String, Value>>>);
+
+/// Lazily loaded variables of all hosts in `bois_dir/hosts`.
+#[derive(Debug)]
+pub struct Hosts {
+    bois_dir: PathBuf,
+    hosts_dir: PathBuf,
+    cache: HostsCache,
+}
+
+impl Hosts {
+    pub fn new(bois_dir: &Path) -> Self {
+        Self::with_cache(bois_dir, HostsCache::default())
+    }
+
+    /// Read the hosts in `bois_dir`, but use and fill an existing cache.
+    pub fn with_cache(bois_dir: &Path, cache: HostsCache) -> Self {
+        Hosts {
+            bois_dir: bois_dir.to_path_buf(),
+            hosts_dir: bois_dir.join("hosts"),
+            cache,
+        }
+    }
+
+    /// The names of all hosts, sorted by name.
+    pub fn names(&self) -> Vec<String> {
+        let Ok(entries) = std::fs::read_dir(&self.hosts_dir) else {
+            return Vec::new();
+        };
+
+        let mut names: Vec<String> = entries
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| entry.path().is_dir())
+            .map(|entry| entry.file_name().to_string_lossy().to_string())
+            .collect();
+        names.sort();
+
+        names
+    }
+
+    /// Read the variables of a host.
+    ///
+    /// These are the variables of all its vars files, see [file_variable_layers], as well as
+    /// `host` and `boi_groups`.
+    /// Group conditions aren't evaluated, as they may depend on facts of the other machine, so
+    /// `boi_groups` contains all groups that're listed in the host's config.
+    pub fn host_vars(&self, name: &str) -> Result<Option<Mapping>> {
+        let host_dir = self.hosts_dir.join(name);
+        // Host names are looked up by templates, so don't allow any paths.
+        if name.contains('/') || name.starts_with('.') || !host_dir.is_dir() {
+            return Ok(None);
+        }
+
+        let layers = file_variable_layers(&self.bois_dir, name)?;
+        let serde_yaml::Value::Mapping(mut variables) = merge_layers(&layers) else {
+            unreachable!("Merged variables are always a mapping");
+        };
+
+        let groups: Vec<String> = match read_yaml::<HostConfig>(&host_dir, "host") {
+            Ok(config) => config
+                .groups
+                .iter()
+                .map(|group| group.name().to_string())
+                .collect(),
+            Err(BoisError::FileNotFound(_, _)) => Vec::new(),
+            Err(err) => bail!(err),
+        };
+
+        variables.insert("host".into(), name.into());
+        variables.insert("boi_groups".into(), serde_yaml::to_value(groups)?);
+
+        Ok(Some(variables))
+    }
+}
+
+impl Object for Hosts {
+    fn repr(self: &Arc<Self>) -> ObjectRepr {
+        ObjectRepr::Map
+    }
+
+    fn get_value(self: &Arc<Self>, key: &Value) -> Option<Value> {
+        let name = key.as_str()?;
+
+        let mut cache = self.cache.0.lock().unwrap();
+        if let Some(value) = cache.get(name) {
+            return Some(value.clone());
+        }
+
+        // Errors are stored as invalid values, which fail the template once they're used.
+        let value = match self.host_vars(name) {
+            Ok(Some(variables)) => Value::from_serialize(variables),
+            Ok(None) => return None,
+            Err(err) => Value::from(Error::new(
+                ErrorKind::InvalidOperation,
+                format!("Failed to read variables of host {name}: {err:#}"),
+            )),
+        };
+        cache.insert(name.to_string(), value.clone());
+
+        Some(value)
+    }
+
+    fn enumerate(self: &Arc<Self>) -> Enumerator {
+        Enumerator::Values(self.names().into_iter().map(Value::from).collect())
+    }
+}
@@ -0,0 +1,58 @@
+//! Lint templates for undefined variables.
+//!
+//! Every variable a template references is resolved against the host's variables, which allows
+//! us to show where it comes from, or to point out typos before anything is rendered.
+use std::collections::BTreeMap;
+
+use anyhow::Result;
+use serde_yaml::Value;
+
+use super::{template_variables, variables::find_source};
+use crate::config::file::Delimiters;
+
+/// A variable that's referenced by a template.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct VariableUsage {
+    /// The full name of the variable, including attribute lookups, e.g. `facts.cpu.vendor`.
+    pub name: String,
+    /// The source that provides the variable, or `None` if it's undefined.
+    pub source: Option<String>,
+}
+
+/// Get all variables that're referenced by a template and the sources that provide them.
+///
+/// `sources` maps variables to their source, see [super::variables::variable_sources].
+/// Nested variables like `hosts.NASboi` may have their own source, which takes precedence over
+/// the source of their parent.
+pub fn lint_template(
+    content: &str,
+    syntax: &Option<Delimiters>,
+    variables: &Value,
+    sources: &BTreeMap<String, String>,
+) -> Result<Vec<VariableUsage>> {
+    let mut usages = Vec::new();
+    for name in template_variables(content, syntax)? {
+        let source = if is_defined(&name, variables) {
+            find_source(&name, sources)
+        } else {
+            None
+        };
+
+        usages.push(VariableUsage { name, source });
+    }
+
+    Ok(usages)
+}
+
+/// Check whether a variable like `machine.threads` exists in the variables.
+fn is_defined(name: &str, variables: &Value) -> bool {
+    let mut value = variables;
+    for key in name.split('.') {
+        match value.get(key) {
+            Some(inner) => value = inner,
+            None => return false,
+        }
+    }
+
+    true
+}
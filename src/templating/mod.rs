@@ -1,18 +1,161 @@
-use anyhow::{Context, Result};
+use std::{
+    collections::{BTreeSet, HashMap},
+    path::PathBuf,
+};
+
+use anyhow::{Context, Result, bail};
 use log::info;
-use minijinja::{Environment, syntax::SyntaxConfig};
+use minijinja::{Environment, UndefinedBehavior, path_loader, syntax::SyntaxConfig};
 use serde_yaml::Value;
 
-use crate::config::file::Delimiters;
+use self::{hosts::HostsCache, password_managers::secret::SecretProvider};
+use crate::config::{
+    bois::Configuration,
+    file::{Delimiters, Undefined},
+};
 
-mod password_managers;
+pub mod facts;
+mod filters;
+pub mod hosts;
+pub mod lint;
+pub mod password_managers;
 pub mod variables;
 
+/// The directory at the root of the bois configuration, which contains shared templates.
+pub const TEMPLATES_DIR: &str = "templates";
+
+/// The parts of the bois configuration that're used while templating.
+#[derive(Clone, Debug, Default)]
+pub struct TemplateContext {
+    /// The root of the bois configuration.
+    /// Shared templates are read from its [TEMPLATES_DIR] and `read_file` reads files inside of
+    /// it.
+    pub bois_dir: PathBuf,
+    /// The environment variables from the `bois.yml`, which are used by the `env` function.
+    pub envs: HashMap<String, String>,
+    /// The variables of other hosts, which are exposed as `hosts`.
+    pub hosts: HostsCache,
+    /// The secret providers from the `bois.yml`, which are used by the `secret` function.
+    pub secret_providers: Vec<SecretProvider>,
+}
+
+impl TemplateContext {
+    pub fn new(config: &Configuration) -> Self {
+        TemplateContext {
+            bois_dir: config.bois_dir.clone(),
+            envs: config.envs.clone(),
+            hosts: HostsCache::default(),
+            secret_providers: config.secret_providers.clone(),
+        }
+    }
+}
+
 /// Take some template text, some values and render the template with the given values.
-pub fn render_template(content: &str, vars: &Value, syntax: &Option<Delimiters>) -> Result<String> {
+///
+/// If a `context` is given, templates inside of its [TEMPLATES_DIR] can be used via
+/// `{% include %}`, `{% import %}` and `{% extends %}`. Those templates use the same syntax as the
+/// file itself.
+///
+/// Undefined variables are an error, unless `undefined` is set to [Undefined::Lenient].
+pub fn render_template(
+    content: &str,
+    vars: &Value,
+    syntax: &Option<Delimiters>,
+    undefined: Undefined,
+    context: Option<&TemplateContext>,
+) -> Result<String> {
+    let mut env = new_environment(syntax, context)?;
+    env.set_undefined_behavior(match undefined {
+        Undefined::Strict => UndefinedBehavior::Strict,
+        Undefined::Lenient => UndefinedBehavior::Lenient,
+    });
+    if let Some(context) = context {
+        env.set_loader(path_loader(context.bois_dir.join(TEMPLATES_DIR)));
+    }
+
+    env.add_template("file", content)
+        .context("Failed to pre-compile template.")?;
+    let template = env.get_template("file").unwrap();
+    let mut rendered = template.render(vars).context("Failed to render template")?;
+    // minijinja doesn't have a trailing newline, which is a bit annoying as many editors add one.
+    rendered.push('\n');
+
+    Ok(rendered)
+}
+
+/// Get all variables a template references, which aren't defined inside the template itself.
+/// Attribute lookups are included, e.g. `machine.threads`.
+///
+/// Functions and other globals of the environment, such as `range` or `env`, aren't variables,
+/// so they're skipped.
+pub fn template_variables(content: &str, syntax: &Option<Delimiters>) -> Result<BTreeSet<String>> {
+    let env = new_environment(syntax, None)?;
+    let template = env
+        .template_from_str(content)
+        .context("Failed to pre-compile template.")?;
+    let globals: BTreeSet<&str> = env.globals().map(|(name, _)| name).collect();
+
+    Ok(template
+        .undeclared_variables(true)
+        .into_iter()
+        .filter(|name| {
+            let root = name.split('.').next().unwrap_or_default();
+            !globals.contains(root)
+        })
+        .collect())
+}
+
+/// Render a templated path or file name, e.g. `/home/{{ user }}/.config` or `{{ host }}.conf`.
+/// Paths always use the default templating syntax.
+///
+/// Undefined variables are always an error, as they would silently deploy to the wrong path.
+pub fn render_path(path: &str, vars: &Value, context: Option<&TemplateContext>) -> Result<String> {
+    // Most paths aren't templated, so don't bother to spin up a template environment.
+    if !path.contains("{{") && !path.contains("{%") {
+        return Ok(path.to_string());
+    }
+
+    let mut env = new_environment(&None, context)?;
+    env.set_undefined_behavior(UndefinedBehavior::Strict);
+    env.render_str(path, vars)
+        .context(format!("Failed to render path {path}"))
+}
+
+/// Evaluate a condition, e.g. `is_laptop and "desktop" not in boi_groups`.
+///
+/// Undefined variables are always an error, as a typo would otherwise silently skip an entry.
+/// Optional variables can still be checked via `some_var is defined`.
+pub fn evaluate_condition(
+    condition: &str,
+    vars: &Value,
+    context: Option<&TemplateContext>,
+) -> Result<bool> {
+    let mut env = new_environment(&None, context)?;
+    env.set_undefined_behavior(UndefinedBehavior::Strict);
+    let expression = env
+        .compile_expression(condition)
+        .context(format!("Failed to compile condition: {condition}"))?;
+    let result = expression
+        .eval(vars)
+        .context(format!("Failed to evaluate condition: {condition}"))?;
+    // A bare undefined variable is returned as is, instead of failing in strict mode.
+    if result.is_undefined() {
+        bail!("Failed to evaluate condition: {condition} is undefined");
+    }
+
+    Ok(result.is_true())
+}
+
+/// Create a new templating environment with all of our functions and the given syntax.
+fn new_environment<'a>(
+    syntax: &Option<Delimiters>,
+    context: Option<&TemplateContext>,
+) -> Result<Environment<'a>> {
     let mut env = Environment::new();
     env.set_trim_blocks(true);
-    password_managers::add_password_manager_functions(&mut env);
+    filters::add_filters(&mut env, context);
+    hosts::add_hosts(&mut env, context);
+    password_managers::add_password_manager_functions(&mut env, context);
 
     if let Some(syntax) = syntax {
         info!("Found custom syntax for template file");
@@ -31,12 +174,5 @@ pub fn render_template(content: &str, vars: &Value, syntax: &Option<Delimiters>)
         );
     }
 
-    env.add_template("file", content)
-        .context("Failed to pre-compile template.")?;
-    let template = env.get_template("file").unwrap();
-    let mut rendered = template.render(vars).context("Failed to render template")?;
-    // minijinja doesn't have a trailing newline, which is a bit annoying as many editors add one.
-    rendered.push('\n');
-
-    Ok(rendered)
+    Ok(env)
 }
@@ -1,8 +1,20 @@
-use minijinja::Environment;
+use minijinja::{Environment, value::Kwargs};
+
+use super::TemplateContext;
 
 mod passwordstore;
+pub mod secret;
 
 /// Add custom filters that provide integration for password managers.
-pub fn add_password_manager_functions(env: &mut Environment) {
+///
+/// `secret` uses the providers of the `context`, if one is given.
+pub fn add_password_manager_functions(env: &mut Environment, context: Option<&TemplateContext>) {
     env.add_function("pass", passwordstore::pass);
+    let context = context.cloned();
+    env.add_function(
+        "secret",
+        move |provider: &str, key: &str, kwargs: Kwargs| {
+            secret::secret(context.as_ref(), provider, key, kwargs)
+        },
+    );
 }
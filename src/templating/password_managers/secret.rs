@@ -0,0 +1,237 @@
+//! Generic secret providers, which are configured in the `bois.yml`.
+//!
+//! Secrets are requested in templates via
+//! ```txt
+//! {{ secret("bitwarden", "github.com") }}
+//! {{ secret("bitwarden", "github.com", field="username") }}
+//! ```
+//!
+//! Every provider calls an executable that prints the secret to stdout.
+use std::{
+    collections::HashMap,
+    process::{Command, Stdio},
+};
+
+use anyhow::{Context, Result, bail};
+use minijinja::{Error, ErrorKind, Value, value::Kwargs};
+use serde::{Deserialize, Serialize};
+
+use crate::templating::TemplateContext;
+
+/// A secret provider, which can be used in templates via its name.
+#[derive(PartialEq, Eq, Clone, Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct SecretProvider {
+    /// The name that's used to reference this provider in templates.
+    pub name: String,
+    /// The backend of this provider.
+    pub kind: SecretProviderKind,
+    /// The executable that's called.
+    /// Defaults to the executable of the backend, e.g. `gopass`, and is required for `command`.
+    #[serde(default)]
+    pub command: Option<String>,
+    /// Arguments that're passed before the key. Only used by the `command` backend.
+    #[serde(default)]
+    pub args: Vec<String>,
+    /// Additional environment variables for this provider.
+    /// These take precedence over the global `envs`.
+    #[serde(default)]
+    pub env: HashMap<String, String>,
+}
+
+/// The supported backends of secret providers.
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SecretProviderKind {
+    /// https://www.passwordstore.org/
+    Pass,
+    /// https://www.gopass.pw/
+    Gopass,
+    /// The unofficial Bitwarden CLI https://github.com/doy/rbw
+    Rbw,
+    /// libsecret, e.g. the GNOME keyring or KeePassXC.
+    /// Keys are whitespace separated `attribute=value` pairs.
+    SecretTool,
+    /// Any command that prints the secret to stdout. The key is passed as the last argument.
+    Command,
+}
+
+impl SecretProviderKind {
+    /// The executable of the backend.
+    fn executable(&self) -> Option<&'static str> {
+        match self {
+            SecretProviderKind::Pass => Some("pass"),
+            SecretProviderKind::Gopass => Some("gopass"),
+            SecretProviderKind::Rbw => Some("rbw"),
+            SecretProviderKind::SecretTool => Some("secret-tool"),
+            SecretProviderKind::Command => None,
+        }
+    }
+
+    /// Whether the backend can look up fields of a secret on its own.
+    /// For all other backends, the output is parsed as yaml and the field is taken from there.
+    fn has_fields(&self) -> bool {
+        matches!(self, SecretProviderKind::Gopass | SecretProviderKind::Rbw)
+    }
+}
+
+impl SecretProvider {
+    /// Make sure the provider can be used.
+    pub fn validate(&self) -> Result<()> {
+        if self.command.is_none() && self.kind == SecretProviderKind::Command {
+            bail!(
+                "Secret provider '{}' of kind 'command' needs a command",
+                self.name
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Get a secret or one of its fields.
+    ///
+    /// `envs` are the global environment variables from the `bois.yml`.
+    pub fn fetch(
+        &self,
+        key: &str,
+        field: Option<&str>,
+        envs: &HashMap<String, String>,
+    ) -> Result<String> {
+        let Some(program) = self.command.as_deref().or(self.kind.executable()) else {
+            bail!("Secret provider '{}' has no command", self.name);
+        };
+        let args = self.args(key, field)?;
+
+        // Stdin and stderr are inherited, so users can unlock their password manager if needed.
+        let output = Command::new(program)
+            .args(&args)
+            .envs(envs)
+            .envs(&self.env)
+            .stdin(Stdio::inherit())
+            .stderr(Stdio::inherit())
+            .output()
+            .context(format!("Failed to execute `{program} {}`", args.join(" ")))?;
+        if !output.status.success() {
+            bail!(
+                "`{program} {}` failed with {}",
+                args.join(" "),
+                output.status
+            );
+        }
+        let content = String::from_utf8_lossy(&output.stdout);
+
+        match field {
+            Some(_) if self.kind.has_fields() => Ok(content.trim_end_matches('\n').to_string()),
+            Some(field) => self.parse_field(&content, field),
+            // Password managers put the password on the first line.
+            None if self.kind.has_fields() || self.kind == SecretProviderKind::Pass => {
+                Ok(content.lines().next().unwrap_or_default().to_string())
+            }
+            None => Ok(content.trim_end_matches('\n').to_string()),
+        }
+    }
+
+    /// The arguments for the backend's executable.
+    fn args(&self, key: &str, field: Option<&str>) -> Result<Vec<String>> {
+        let args = match (self.kind, field) {
+            (SecretProviderKind::Pass, _) => vec!["show".into(), key.into()],
+            (SecretProviderKind::Gopass, Some(field)) => {
+                vec!["show".into(), key.into(), field.into()]
+            }
+            (SecretProviderKind::Gopass, None) => {
+                vec!["show".into(), "--password".into(), key.into()]
+            }
+            (SecretProviderKind::Rbw, Some(field)) => {
+                vec!["get".into(), "--field".into(), field.into(), key.into()]
+            }
+            (SecretProviderKind::Rbw, None) => vec!["get".into(), key.into()],
+            (SecretProviderKind::SecretTool, _) => {
+                let mut args = vec!["lookup".to_string()];
+                for pair in key.split_whitespace() {
+                    let Some((attribute, value)) = pair.split_once('=') else {
+                        bail!("Expected 'attribute=value' pairs for secret-tool, got '{key}'");
+                    };
+                    args.push(attribute.into());
+                    args.push(value.into());
+                }
+                args
+            }
+            (SecretProviderKind::Command, _) => {
+                let mut args = self.args.clone();
+                args.push(key.into());
+                args
+            }
+        };
+
+        Ok(args)
+    }
+
+    /// Take a field from yaml output.
+    ///
+    /// `pass` entries have the password on the first line, so only the lines after it are parsed.
+    /// The password and the yaml may be separated by a `===` line.
+    fn parse_field(&self, content: &str, field: &str) -> Result<String> {
+        let yaml = match self.kind {
+            SecretProviderKind::Pass => {
+                let other_lines = content
+                    .split_once('\n')
+                    .map(|(_, other_lines)| other_lines)
+                    .unwrap_or_default();
+                other_lines.strip_prefix("===\n").unwrap_or(other_lines)
+            }
+            _ => content,
+        };
+
+        let value: serde_yaml::Value = serde_yaml::from_str(yaml).context(format!(
+            "Failed to parse yaml from provider '{}'",
+            self.name
+        ))?;
+        match value.get(field) {
+            Some(serde_yaml::Value::String(value)) => Ok(value.clone()),
+            Some(value) => Ok(serde_yaml::to_string(value)?.trim_end().to_string()),
+            None => bail!("Secret has no field '{field}'"),
+        }
+    }
+}
+
+/// Get a secret from one of the providers in the `bois.yml`.
+///
+/// ```txt
+/// {{ secret("work", "ssh/key", field="passphrase") }}
+/// ```
+///
+/// The providers and global environment variables are taken from the `context`.
+pub fn secret(
+    context: Option<&TemplateContext>,
+    provider: &str,
+    key: &str,
+    kwargs: Kwargs,
+) -> Result<Value, Error> {
+    let field: Option<&str> = kwargs.get("field")?;
+    kwargs.assert_all_used()?;
+
+    let context = context.ok_or_else(|| {
+        Error::new(
+            ErrorKind::InvalidOperation,
+            "Secrets can only be used with a configuration",
+        )
+    })?;
+    let Some(secret_provider) = context
+        .secret_providers
+        .iter()
+        .find(|secret_provider| secret_provider.name == provider)
+    else {
+        return Err(Error::new(
+            ErrorKind::InvalidOperation,
+            format!("Found no secret provider with name '{provider}'"),
+        ));
+    };
+
+    match secret_provider.fetch(key, field, &context.envs) {
+        Ok(secret) => Ok(secret.into()),
+        Err(err) => Err(Error::new(
+            ErrorKind::InvalidOperation,
+            format!("Failed to get secret '{key}' from provider '{provider}': {err:#}"),
+        )),
+    }
+}
@@ -1,35 +1,433 @@
-use std::{env::var, path::Path};
+use std::{
+    collections::BTreeMap,
+    env::var,
+    path::{Path, PathBuf},
+};
 
-use anyhow::{Result, bail};
+use anyhow::{Context, Result, bail};
 use nix::unistd::{Gid, Uid};
+use serde::{Deserialize, Serialize};
 use serde_yaml::{Mapping, Value};
 
-use crate::{config::helper::read_yaml, state::host::HostConfig};
+use super::facts::gather_facts;
+use crate::{config::helper::read_yaml, error::Error, state::format::deep_merge};
 
-/// Read the `vars.yml` from a host directory if it exists.
+/// The source of variables that're provided by bois itself.
+pub const BUILTIN_SOURCE: &str = "built-in";
+/// The source of the `facts` variable, see [gather_facts].
+pub const FACTS_SOURCE: &str = "fact";
+
+/// The prefix of environment variables that override template variables.
+pub const ENV_PREFIX: &str = "BOIS_VAR_";
+
+/// A set of template variables and where they come from.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct VariableLayer {
+    /// A human readable description of the source, e.g. the path of a vars file.
+    pub source: String,
+    /// The kind of source, which decides where group variables are placed.
+    pub scope: VariableScope,
+    pub variables: Mapping,
+}
+
+/// The kind of source a [VariableLayer] comes from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum VariableScope {
+    /// Vars files at the root of the bois directory, which are shared by all hosts.
+    Global,
+    /// Vars files of a group.
+    Group,
+    /// Vars files of the host.
+    Host,
+    /// Variables that're provided by bois itself, including facts.
+    Builtin,
+    /// Overrides from the command line or the environment.
+    Override,
+}
+
+/// The directory next to a `vars.yml`, whose files are merged on top of it.
+pub const VARS_DIR: &str = "vars.d";
+
+/// Read all variables of a host, see [host_variable_layers].
+///
+/// The enabled groups are added via [insert_groups], as they depend on these variables.
+pub fn get_host_vars(root: &Path, hostname: &str, overrides: &[VariableLayer]) -> Result<Value> {
+    let layers = host_variable_layers(root, hostname, overrides)?;
+
+    Ok(merge_layers(&layers))
+}
+
+/// Get all layers of variables for a host, ordered by precedence.
 ///
-/// While at it, populate the variables with other useful variables that're exposed by defaults.
-/// These include:
+/// These are the [file_variable_layers], followed by variables that're exposed by default:
 /// - The hostname itself
-pub fn get_host_vars(host_dir: &Path, hostname: &str, config: &HostConfig) -> Result<Value> {
-    // First up, read the vars.yml file and convert it into a [serde_yaml::Value].
-    let vars_file_exists =
-        host_dir.join("vars.yaml").exists() || host_dir.join("vars.yml").exists();
-
-    // We expect vars to a top level map, so yamls consisting of a single array will throw an
-    // error. If no file is found, return an empty map.
-    let mut variables = if vars_file_exists {
-        let value = read_yaml::<Value>(host_dir, "vars")?;
-        match value {
-            Value::Mapping(map) => map,
-            _ => bail!("Expected map for variables. Got {value:#?}"),
+/// - The current user and its ids
+/// - Facts about the current system, see [gather_facts]
+///
+/// The `overrides` from the command line and environment come last, see [override_layers].
+pub fn host_variable_layers(
+    root: &Path,
+    hostname: &str,
+    overrides: &[VariableLayer],
+) -> Result<Vec<VariableLayer>> {
+    let mut layers = file_variable_layers(root, hostname)?;
+    layers.push(VariableLayer {
+        source: BUILTIN_SOURCE.to_string(),
+        scope: VariableScope::Builtin,
+        variables: builtin_vars(hostname),
+    });
+
+    // Insert facts about the system, such as the OS or the hardware.
+    let mut facts = Mapping::new();
+    facts.insert("facts".into(), serde_yaml::to_value(gather_facts())?);
+    layers.push(VariableLayer {
+        source: FACTS_SOURCE.to_string(),
+        scope: VariableScope::Builtin,
+        variables: facts,
+    });
+
+    layers.extend(overrides.iter().cloned());
+
+    Ok(layers)
+}
+
+/// Get all variable overrides, ordered by precedence.
+///
+/// 1. `BOIS_VAR_*` environment variables, see [env_override_layers].
+/// 2. `--vars-file` files in the given order.
+/// 3. `--var key=value` arguments in the given order, see [parse_override].
+pub fn override_layers(vars: &[String], vars_files: &[PathBuf]) -> Result<Vec<VariableLayer>> {
+    let mut layers = env_override_layers(std::env::vars())?;
+
+    for path in vars_files {
+        let content = std::fs::read_to_string(path)
+            .map_err(|err| Error::IoPath(path.clone(), "reading vars file", err))?;
+        let value: Value = serde_yaml::from_str(&content)
+            .map_err(|err| Error::Deserialization(path.clone(), err))?;
+        let variables = match value {
+            Value::Mapping(variables) => variables,
+            Value::Null => Mapping::new(),
+            _ => bail!("Expected map for variables in {path:?}. Got {value:#?}"),
+        };
+        layers.push(VariableLayer {
+            source: path.to_string_lossy().to_string(),
+            scope: VariableScope::Override,
+            variables,
+        });
+    }
+
+    for var in vars {
+        let (key, value) = var
+            .split_once('=')
+            .context(format!("Expected `--var key=value`. Got '{var}'"))?;
+        layers.push(VariableLayer {
+            source: format!("--var {key}"),
+            scope: VariableScope::Override,
+            variables: parse_override(key, value)?,
+        });
+    }
+
+    Ok(layers)
+}
+
+/// Get the overrides of all `BOIS_VAR_*` environment variables, sorted by name.
+///
+/// As environment variables can't contain dots, nested keys are separated by `__`.
+/// E.g. `BOIS_VAR_machine__threads=16` sets `machine.threads`.
+pub fn env_override_layers(
+    envs: impl IntoIterator<Item = (String, String)>,
+) -> Result<Vec<VariableLayer>> {
+    let mut envs: Vec<(String, String)> = envs
+        .into_iter()
+        .filter(|(name, _)| name.starts_with(ENV_PREFIX) && name.len() > ENV_PREFIX.len())
+        .collect();
+    envs.sort();
+
+    let mut layers = Vec::new();
+    for (name, value) in envs {
+        let key = name[ENV_PREFIX.len()..].replace("__", ".");
+        layers.push(VariableLayer {
+            variables: parse_override(&key, &value)
+                .context(format!("Invalid environment variable {name}"))?,
+            source: format!("${name}"),
+            scope: VariableScope::Override,
+        });
+    }
+
+    Ok(layers)
+}
+
+/// Build the variables for a single override like `machine.threads=16`.
+///
+/// Dots in the key create nested maps. The value is parsed as a yaml scalar, so `true` or `16`
+/// become a boolean or number. Everything else, including lists and maps, is kept as a string.
+pub fn parse_override(key: &str, value: &str) -> Result<Mapping> {
+    if key.is_empty() || key.split('.').any(str::is_empty) {
+        bail!("Invalid variable name '{key}'");
+    }
+
+    let mut value = match serde_yaml::from_str::<Value>(value) {
+        Ok(parsed @ (Value::Bool(_) | Value::Number(_) | Value::String(_))) => parsed,
+        _ => Value::String(value.to_string()),
+    };
+
+    // Build the nested maps from the inside out.
+    let mut keys: Vec<&str> = key.split('.').collect();
+    let last = keys.pop().unwrap_or_default();
+    let mut variables = Mapping::new();
+    variables.insert(last.into(), value);
+    for key in keys.into_iter().rev() {
+        value = Value::Mapping(variables);
+        variables = Mapping::new();
+        variables.insert(key.into(), value);
+    }
+
+    Ok(variables)
+}
+
+/// Read all vars files of a host, ordered by precedence.
+///
+/// 1. `vars.yml` at the root of the bois directory, which is shared by all hosts.
+/// 2. `vars.d/*.yml` at the root of the bois directory, sorted by file name.
+/// 3. `hosts/<name>/vars.yml`
+/// 4. `hosts/<name>/vars.d/*.yml`, sorted by file name.
+pub fn file_variable_layers(root: &Path, hostname: &str) -> Result<Vec<VariableLayer>> {
+    let mut layers = directory_variable_layers(root, VariableScope::Global)?;
+    layers.extend(directory_variable_layers(
+        &root.join("hosts").join(hostname),
+        VariableScope::Host,
+    )?);
+
+    Ok(layers)
+}
+
+/// Get the variable layers for the files of a group.
+///
+/// The group's `vars.yml` and `vars.d/*.yml` are placed between the global variables at the
+/// root of the bois directory and the host's variables. That way, hosts can still override the
+/// variables of their groups.
+pub fn group_variable_layers(
+    root: &Path,
+    group: &str,
+    host_layers: &[VariableLayer],
+) -> Result<Vec<VariableLayer>> {
+    let group_layers =
+        directory_variable_layers(&root.join("groups").join(group), VariableScope::Group)?;
+
+    // Global layers always come first, see [file_variable_layers].
+    let global_count = host_layers
+        .iter()
+        .take_while(|layer| layer.scope == VariableScope::Global)
+        .count();
+
+    let mut layers = host_layers.to_vec();
+    layers.splice(global_count..global_count, group_layers);
+
+    Ok(layers)
+}
+
+/// Get the variables for the files of a group, see [group_variable_layers].
+pub fn group_variables(
+    root: &Path,
+    group: &str,
+    host_layers: &[VariableLayer],
+    group_names: &[String],
+) -> Result<Value> {
+    let layers = group_variable_layers(root, group, host_layers)?;
+    let mut variables = merge_layers(&layers);
+    insert_groups(&mut variables, group_names);
+
+    Ok(variables)
+}
+
+/// Read the `vars.yml` and all `vars.d/*.yml` files of a directory.
+fn directory_variable_layers(directory: &Path, scope: VariableScope) -> Result<Vec<VariableLayer>> {
+    let mut layers = Vec::new();
+    if let Some(layer) = read_vars_file(directory, "vars", scope)? {
+        layers.push(layer);
+    }
+
+    let vars_dir = directory.join(VARS_DIR);
+    for name in vars_file_names(&vars_dir)? {
+        if let Some(layer) = read_vars_file(&vars_dir, &name, scope)? {
+            layers.push(layer);
         }
-    } else {
-        Mapping::new()
+    }
+
+    Ok(layers)
+}
+
+/// Read a single vars file via [read_yaml], if it exists.
+/// We expect vars to be a top level map, so yamls consisting of a single array will throw an
+/// error.
+fn read_vars_file(
+    directory: &Path,
+    name: &str,
+    scope: VariableScope,
+) -> Result<Option<VariableLayer>> {
+    let value = match read_yaml::<Value>(directory, name) {
+        Ok(value) => value,
+        Err(Error::FileNotFound(_, _)) => return Ok(None),
+        Err(err) => bail!(err),
     };
 
-    // ----------- Default template variables -----------
-    // The following block injects default variables that're always available during templating.
+    let mut path = directory.join(format!("{name}.yml"));
+    if !path.exists() {
+        path.set_extension("yaml");
+    }
+
+    let variables = match value {
+        Value::Mapping(variables) => variables,
+        // Empty files are fine.
+        Value::Null => Mapping::new(),
+        _ => bail!("Expected map for variables in {path:?}. Got {value:#?}"),
+    };
+
+    Ok(Some(VariableLayer {
+        source: path.to_string_lossy().to_string(),
+        scope,
+        variables,
+    }))
+}
+
+/// Get the names of all yaml files in a `vars.d` directory without their extension, sorted by
+/// name.
+fn vars_file_names(directory: &Path) -> Result<Vec<String>> {
+    if !directory.is_dir() {
+        return Ok(Vec::new());
+    }
+
+    let entries = std::fs::read_dir(directory)
+        .map_err(|err| Error::IoPath(directory.to_path_buf(), "reading directory", err))?;
+    let mut names = Vec::new();
+    for entry in entries {
+        let entry =
+            entry.map_err(|err| Error::IoPath(directory.to_path_buf(), "reading entry", err))?;
+        let path = entry.path();
+        let is_yaml = path
+            .extension()
+            .is_some_and(|extension| extension == "yml" || extension == "yaml");
+        if !path.is_file() || !is_yaml {
+            continue;
+        }
+
+        if let Some(stem) = path.file_stem() {
+            names.push(stem.to_string_lossy().to_string());
+        }
+    }
+    names.sort();
+    names.dedup();
+
+    Ok(names)
+}
+
+/// Deep-merge all layers into a single set of variables.
+/// Mappings are merged key by key, all other values of later layers replace earlier ones.
+pub fn merge_layers(layers: &[VariableLayer]) -> Value {
+    let mut variables = Value::Mapping(Mapping::new());
+    for layer in layers {
+        deep_merge(&mut variables, Value::Mapping(layer.variables.clone()));
+    }
+
+    variables
+}
+
+/// Get the source of each variable, including nested variables like `machine.threads`.
+/// If a variable is defined in several layers, the last one wins.
+pub fn variable_sources(layers: &[VariableLayer]) -> BTreeMap<String, String> {
+    let mut sources = BTreeMap::new();
+    for layer in layers {
+        insert_sources(&mut sources, None, &layer.variables, &layer.source);
+    }
+
+    sources
+}
+
+/// Recursively insert the sources of all variables of a mapping.
+fn insert_sources(
+    sources: &mut BTreeMap<String, String>,
+    prefix: Option<&str>,
+    variables: &Mapping,
+    source: &str,
+) {
+    for (key, value) in variables {
+        let Some(key) = key.as_str() else {
+            continue;
+        };
+        let path = match prefix {
+            Some(prefix) => format!("{prefix}.{key}"),
+            None => key.to_string(),
+        };
+
+        match value {
+            Value::Mapping(mapping) => insert_sources(sources, Some(&path), mapping, source),
+            // Any other value replaces everything that's been nested below it.
+            _ => {
+                let nested = format!("{path}.");
+                sources.retain(|name, _| !name.starts_with(&nested));
+            }
+        }
+        sources.insert(path, source.to_string());
+    }
+}
+
+/// Find the source of the most specific variable, e.g. `hosts.NASboi` before `hosts` for
+/// `hosts.NASboi.ip`.
+pub fn find_source(name: &str, sources: &BTreeMap<String, String>) -> Option<String> {
+    let mut prefix = name;
+    loop {
+        if let Some(source) = sources.get(prefix) {
+            return Some(source.clone());
+        }
+        let (parent, _) = prefix.rsplit_once('.')?;
+        prefix = parent;
+    }
+}
+
+/// Annotate every leaf of the variables with its source.
+///
+/// Mappings are kept, while all other values are replaced by a `{value, source}` mapping.
+pub fn annotate_sources(variables: &Value, sources: &BTreeMap<String, String>) -> Value {
+    annotate(variables, None, sources)
+}
+
+fn annotate(value: &Value, path: Option<&str>, sources: &BTreeMap<String, String>) -> Value {
+    if let Value::Mapping(mapping) = value {
+        let mut annotated = Mapping::new();
+        for (key, value) in mapping {
+            let name = key_name(key);
+            let nested = match path {
+                Some(path) => format!("{path}.{name}"),
+                None => name,
+            };
+            annotated.insert(key.clone(), annotate(value, Some(&nested), sources));
+        }
+        return Value::Mapping(annotated);
+    }
+
+    let source = path.and_then(|path| find_source(path, sources));
+    let mut annotated = Mapping::new();
+    annotated.insert("value".into(), value.clone());
+    annotated.insert("source".into(), serde_yaml::to_value(source).unwrap());
+
+    Value::Mapping(annotated)
+}
+
+/// The name of a key for variable paths. Non-string keys are serialized.
+fn key_name(key: &Value) -> String {
+    match key {
+        Value::String(key) => key.clone(),
+        key => serde_yaml::to_string(key)
+            .unwrap_or_default()
+            .trim()
+            .to_string(),
+    }
+}
+
+/// The default variables that're always available during templating.
+fn builtin_vars(hostname: &str) -> Mapping {
+    let mut variables = Mapping::new();
 
     // Insert the host variables
     variables.insert(
@@ -37,12 +435,6 @@ pub fn get_host_vars(host_dir: &Path, hostname: &str, config: &HostConfig) -> Re
         serde_yaml::to_value(hostname).unwrap(),
     );
 
-    // Insert the list of all enabled groups for this host.
-    variables.insert(
-        serde_yaml::to_value("boi_groups").unwrap(),
-        serde_yaml::to_value(config.groups.clone()).unwrap(),
-    );
-
     // Insert environment dependant variables, specifically which user currently executes boi.
     variables.insert(
         serde_yaml::to_value("USER_ID").unwrap(),
@@ -57,5 +449,16 @@ pub fn get_host_vars(host_dir: &Path, hostname: &str, config: &HostConfig) -> Re
         serde_yaml::to_value(Gid::current().as_raw()).unwrap(),
     );
 
-    Ok(Value::Mapping(variables))
+    variables
+}
+
+/// Insert the list of all enabled groups for this host.
+/// This can only be done once all group conditions have been evaluated.
+pub fn insert_groups(variables: &mut Value, groups: &[String]) {
+    if let Value::Mapping(variables) = variables {
+        variables.insert(
+            serde_yaml::to_value("boi_groups").unwrap(),
+            serde_yaml::to_value(groups).unwrap(),
+        );
+    }
 }
@@ -5,7 +5,7 @@ use comfy_table::{Attribute, Cell, CellAlignment, Column, ContentArrangement, Ta
 use crossterm::style::Stylize;
 
 use crate::{
-    changeset::{PackageInstall, PackageUninstall, PathOperation},
+    changeset::{ChangeHook, PackageInstall, PackageUninstall, PathOperation},
     config::bois::Configuration,
     constants::{CURRENT_GROUP, CURRENT_USER},
     error::Error,
@@ -141,7 +141,14 @@ pub fn print_path_changes(changes: &[PathOperation], config: &Configuration) ->
                         print_file_diff(path, &temp_path)?;
                     }
                 }
-                crate::changeset::FileOperation::Delete { .. } => continue,
+                crate::changeset::FileOperation::Delete { path } => {
+                    println!(
+                        "{} {}:   {}",
+                        "Delete".red().bold(),
+                        "file".bold(),
+                        style_path(path)
+                    );
+                }
             },
             PathOperation::Directory(op) => match op {
                 crate::changeset::DirectoryOperation::Create {
@@ -198,7 +205,14 @@ pub fn print_path_changes(changes: &[PathOperation], config: &Configuration) ->
                         print_table(table);
                     }
                 }
-                crate::changeset::DirectoryOperation::Delete { .. } => continue,
+                crate::changeset::DirectoryOperation::Delete { path } => {
+                    println!(
+                        "{} {}: {}",
+                        "Delete".red().bold(),
+                        "directory".bold(),
+                        path.to_string_lossy(),
+                    );
+                }
             },
         }
 
@@ -210,6 +224,25 @@ pub fn print_path_changes(changes: &[PathOperation], config: &Configuration) ->
     Ok(())
 }
 
+pub fn print_change_hooks(hooks: &[ChangeHook]) {
+    print_header("Change hooks");
+
+    for hook in hooks.iter() {
+        println!("{} {}", "Run".blue().bold(), hook.command.clone().bold());
+        for path in hook.paths.iter() {
+            println!("  {} {}", "triggered by".dim(), path.to_string_lossy());
+        }
+    }
+}
+
+pub fn print_deploy_hooks(header: &str, hooks: &[String]) {
+    print_header(header);
+
+    for hook in hooks.iter() {
+        println!("{} {}", "Run".blue().bold(), hook.clone().bold());
+    }
+}
+
 fn print_header(header: &str) {
     let mut table = Table::new();
     table.set_content_arrangement(ContentArrangement::DynamicFullWidth);
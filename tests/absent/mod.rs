@@ -0,0 +1,69 @@
+use pretty_assertions::assert_eq;
+use testresult::TestResult;
+
+use crate::helper::{deploy_config, operations, plan, write_files};
+
+/// Absent paths that exist on the system are removed, directories including their content.
+#[test]
+fn remove_absent_paths() -> TestResult {
+    let root = tempfile::tempdir()?;
+    write_files(
+        root.path(),
+        &[
+            (
+                "bois/hosts/potato/host.yml",
+                "absent: [motd, old.d, missing.conf]\n",
+            ),
+            (
+                "bois/hosts/potato/legacy.conf",
+                "# bois_config\n# state: absent\n# on_change: [systemctl restart legacy]\n\
+                 # bois_config\n",
+            ),
+            ("target/motd", "Welcome\n"),
+            ("target/old.d/x.conf", "x\n"),
+            ("target/legacy.conf", "legacy\n"),
+        ],
+    )?;
+    let config = deploy_config(root.path());
+
+    let target = root.path().join("target");
+    let changeset = plan(&config)?;
+    assert_eq!(
+        operations(&changeset),
+        vec![
+            ("delete", target.join("motd")),
+            ("delete", target.join("old.d/x.conf")),
+            ("delete", target.join("old.d")),
+            ("delete", target.join("legacy.conf")),
+        ]
+    );
+    // Hooks only run for created or modified files.
+    assert!(changeset.change_hooks.is_empty());
+
+    Ok(())
+}
+
+/// A path can't be deployed and absent at the same time.
+#[test]
+fn deployed_and_absent() -> TestResult {
+    let root = tempfile::tempdir()?;
+    write_files(
+        root.path(),
+        &[
+            ("bois/hosts/potato/host.yml", "absent: [motd, old.d]\n"),
+            ("bois/hosts/potato/motd", "Welcome\n"),
+            ("bois/hosts/potato/old.d/x.conf", "x\n"),
+        ],
+    )?;
+    let config = deploy_config(root.path());
+
+    let Err(err) = plan(&config) else {
+        panic!("Planning should fail for paths that are deployed and absent");
+    };
+    let message = err.to_string();
+    assert!(message.contains("both deployed and absent"), "{message}");
+    assert!(message.contains("motd"), "{message}");
+    assert!(message.contains("old.d/x.conf"), "{message}");
+
+    Ok(())
+}
@@ -0,0 +1,88 @@
+use std::path::PathBuf;
+
+use bois::changeset::Changeset;
+use pretty_assertions::assert_eq;
+use testresult::TestResult;
+
+use crate::helper::{deploy_config, plan, write_files};
+
+/// Hooks are deduplicated by their command, while all triggering paths are kept.
+#[test]
+fn deduplicate_hooks() {
+    let mut changeset = Changeset::new();
+    changeset.add_change_hook("systemctl restart sshd", "/etc/ssh".into());
+    changeset.add_change_hook("systemctl restart sshd", "/etc/ssh/sshd_config".into());
+    changeset.add_change_hook("systemctl restart sshd", "/etc/ssh".into());
+
+    let mut other = Changeset::new();
+    other.add_change_hook("systemctl restart sshd", "/etc/ssh/moduli".into());
+    other.add_change_hook("sysctl --system", "/etc/sysctl.d".into());
+    changeset.merge(other);
+
+    assert_eq!(changeset.change_hooks.len(), 2);
+    assert_eq!(
+        changeset.change_hooks[0].paths,
+        vec![
+            PathBuf::from("/etc/ssh"),
+            PathBuf::from("/etc/ssh/sshd_config"),
+            PathBuf::from("/etc/ssh/moduli"),
+        ]
+    );
+    assert_eq!(changeset.change_hooks[1].command, "sysctl --system");
+}
+
+/// The hook of a directory runs once, even if several files inside of it change.
+#[test]
+fn directory_hook_runs_once() -> TestResult {
+    let root = tempfile::tempdir()?;
+    write_files(
+        root.path(),
+        &[
+            ("bois/hosts/potato/host.yml", "groups: []\n"),
+            (
+                "bois/hosts/potato/app/bois.yml",
+                "on_change: [reload app]\n",
+            ),
+            ("bois/hosts/potato/app/a.conf", "a\n"),
+            ("bois/hosts/potato/app/b.conf", "b\n"),
+            ("target/app/a.conf", "old\n"),
+        ],
+    )?;
+    let config = deploy_config(root.path());
+
+    let changeset = plan(&config)?;
+    assert_eq!(changeset.change_hooks.len(), 1);
+    assert_eq!(changeset.change_hooks[0].command, "reload app");
+
+    let mut paths = changeset.change_hooks[0].paths.clone();
+    paths.sort();
+    let target = root.path().join("target/app");
+    assert_eq!(paths, vec![target.join("a.conf"), target.join("b.conf")]);
+
+    Ok(())
+}
+
+/// Hooks aren't run, if nothing has changed.
+#[test]
+fn no_hook_without_change() -> TestResult {
+    let root = tempfile::tempdir()?;
+    write_files(
+        root.path(),
+        &[
+            ("bois/hosts/potato/host.yml", "groups: []\n"),
+            (
+                "bois/hosts/potato/app/bois.yml",
+                "on_change: [reload app]\n",
+            ),
+            ("bois/hosts/potato/app/a.conf", "a\n"),
+            ("target/app/a.conf", "a\n"),
+        ],
+    )?;
+    let config = deploy_config(root.path());
+
+    let changeset = plan(&config)?;
+    assert!(changeset.path_operations.is_empty());
+    assert!(changeset.change_hooks.is_empty());
+
+    Ok(())
+}
@@ -0,0 +1,84 @@
+use std::{fs, path::Path};
+
+use bois::{
+    state::{directory::read_directory, file::Entry},
+    templating::evaluate_condition,
+};
+use pretty_assertions::assert_eq;
+use testresult::TestResult;
+
+/// Files and directories whose condition isn't met are skipped.
+#[test]
+fn skip_entries() -> TestResult {
+    let root = tempfile::tempdir()?;
+    let directory = root.path().join("config");
+    fs::create_dir_all(directory.join("laptop"))?;
+    fs::create_dir_all(directory.join("desktop"))?;
+    fs::write(directory.join("laptop/bois.yml"), "when: is_laptop\n")?;
+    fs::write(directory.join("laptop/tlp.conf"), "content")?;
+    fs::write(directory.join("desktop/bois.yml"), "when: not is_laptop\n")?;
+    fs::write(directory.join("desktop/file"), "content")?;
+    fs::write(
+        directory.join("battery.conf"),
+        "# bois_config\n# when: is_laptop and battery > 1\n# bois_config\ncontent",
+    )?;
+    fs::write(
+        directory.join("gpu.conf"),
+        "# bois_config\n# when: '\"nvidia\" in gpus'\n# bois_config\ncontent",
+    )?;
+
+    let vars = serde_yaml::from_str("is_laptop: true\nbattery: 1\ngpus: [nvidia]")?;
+    let directory = read_directory(root.path(), Path::new("config"), None, &vars, None)?
+        .expect("Directory has no condition");
+
+    let mut names: Vec<_> = directory
+        .entries
+        .iter()
+        .map(|entry| match entry {
+            Entry::File(file) => file.relative_path.clone(),
+            Entry::Directory(dir) => dir.relative_path.clone(),
+        })
+        .collect();
+    names.sort();
+
+    assert_eq!(
+        names,
+        vec![Path::new("config/gpu.conf"), Path::new("config/laptop")]
+    );
+
+    Ok(())
+}
+
+/// The directory itself is skipped, if its condition isn't met.
+#[test]
+fn skip_directory() -> TestResult {
+    let root = tempfile::tempdir()?;
+    fs::create_dir(root.path().join("config"))?;
+    fs::write(root.path().join("config/bois.yml"), "when: is_laptop\n")?;
+
+    let vars = serde_yaml::from_str("is_laptop: false")?;
+    let directory = read_directory(root.path(), Path::new("config"), None, &vars, None)?;
+    assert!(directory.is_none());
+
+    Ok(())
+}
+
+/// Conditions with undefined variables fail, unless they explicitly check for them.
+#[test]
+fn undefined_condition_variables() -> TestResult {
+    let vars = serde_yaml::from_str("is_laptop: true")?;
+    assert!(evaluate_condition("is_laptop", &vars, None)?);
+    assert!(!evaluate_condition(
+        "gpus is defined and \"nvidia\" in gpus",
+        &vars,
+        None
+    )?);
+
+    let err =
+        evaluate_condition("is_laptpo", &vars, None).expect_err("Undefined variables must fail");
+    assert!(format!("{err:#}").contains("Failed to evaluate condition"));
+    assert!(evaluate_condition("is_laptpo and true", &vars, None).is_err());
+    assert!(evaluate_condition("\"nvidia\" in gpus", &vars, None).is_err());
+
+    Ok(())
+}
@@ -0,0 +1,35 @@
+use pretty_assertions::assert_eq;
+use testresult::TestResult;
+
+use crate::helper::{deploy_config, operations, plan, write_files};
+
+/// Missing create_only files are created, while existing ones keep their content.
+#[test]
+fn seed_missing_files() -> TestResult {
+    let root = tempfile::tempdir()?;
+    let header = "# bois_config\n# create_only: true\n# bois_config\n";
+    write_files(
+        root.path(),
+        &[
+            ("bois/hosts/potato/host.yml", "groups: []\n"),
+            (
+                "bois/hosts/potato/existing.conf",
+                &format!("{header}default\n"),
+            ),
+            (
+                "bois/hosts/potato/missing.conf",
+                &format!("{header}default\n"),
+            ),
+            ("target/existing.conf", "changed by the user\n"),
+        ],
+    )?;
+    let config = deploy_config(root.path());
+
+    let changeset = plan(&config)?;
+    assert_eq!(
+        operations(&changeset),
+        vec![("create", root.path().join("target/missing.conf"))]
+    );
+
+    Ok(())
+}
@@ -0,0 +1,113 @@
+use std::fs;
+
+use bois::{
+    changeset::{FileOperation, PathOperation},
+    templating::hosts::Hosts,
+};
+use minijinja::{Environment, UndefinedBehavior, Value};
+use pretty_assertions::assert_eq;
+use testresult::TestResult;
+
+use crate::helper::{deploy_config, plan, write_files};
+
+/// Create a bois directory with two hosts and an environment that exposes them as `hosts`.
+fn environment(root: &std::path::Path) -> TestResult<Environment<'static>> {
+    fs::create_dir_all(root.join("hosts/NASboi"))?;
+    fs::create_dir_all(root.join("hosts/laptop"))?;
+    fs::write(
+        root.join("hosts/NASboi/vars.yml"),
+        "ip: 10.0.0.2\nshares:\n  - media\n  - backup\n",
+    )?;
+    fs::write(
+        root.join("hosts/NASboi/host.yml"),
+        "groups:\n  - base\n  - name: nas\n    when: is_nas\n",
+    )?;
+
+    let mut env = Environment::new();
+    env.set_undefined_behavior(UndefinedBehavior::Strict);
+    env.add_global("hosts", Value::from_object(Hosts::new(root)));
+
+    Ok(env)
+}
+
+/// The variables of other hosts are available by their name.
+#[test]
+fn host_variables() -> TestResult {
+    let root = tempfile::tempdir()?;
+    let env = environment(root.path())?;
+
+    let rendered = env.render_str(
+        "{{ hosts.NASboi.ip }} {{ hosts.NASboi.shares | join(',') }} {{ hosts.NASboi.host }}",
+        (),
+    )?;
+    assert_eq!(rendered, "10.0.0.2 media,backup NASboi");
+
+    // Group conditions aren't evaluated for other hosts.
+    let rendered = env.render_str("{{ hosts.NASboi.boi_groups | join(',') }}", ())?;
+    assert_eq!(rendered, "base,nas");
+
+    // Hosts without any vars still exist.
+    let rendered = env.render_str("{{ hosts.laptop.host }}", ())?;
+    assert_eq!(rendered, "laptop");
+
+    let rendered = env.render_str("{% for name in hosts %}{{ name }} {% endfor %}", ())?;
+    assert_eq!(rendered, "NASboi laptop ");
+
+    Ok(())
+}
+
+/// Unknown hosts are undefined and invalid vars files fail once they're used.
+#[test]
+fn invalid_hosts() -> TestResult {
+    let root = tempfile::tempdir()?;
+    let env = environment(root.path())?;
+
+    assert!(env.render_str("{{ hosts.missing.ip }}", ()).is_err());
+    assert!(
+        env.render_str("{{ hosts['../hosts/NASboi'].ip }}", ())
+            .is_err()
+    );
+    assert_eq!(
+        env.render_str("{{ hosts.missing is defined }}", ())?,
+        "false"
+    );
+
+    fs::create_dir_all(root.path().join("hosts/broken"))?;
+    fs::write(root.path().join("hosts/broken/vars.yml"), "- not a map")?;
+    assert!(env.render_str("{{ hosts.broken.ip }}", ()).is_err());
+
+    Ok(())
+}
+
+/// Deployed files can use the variables of other hosts of the same bois directory.
+#[test]
+fn render_deployed_file() -> TestResult {
+    let root = tempfile::tempdir()?;
+    write_files(
+        root.path(),
+        &[
+            ("bois/hosts/potato/host.yml", "groups: []\n"),
+            (
+                "bois/hosts/potato/nas.conf",
+                "# bois_config\n# template: true\n# bois_config\nserver={{ hosts.NASboi.ip }}\n",
+            ),
+            ("bois/hosts/NASboi/vars.yml", "ip: 10.0.0.2\n"),
+        ],
+    )?;
+    let config = deploy_config(root.path());
+
+    let changeset = plan(&config)?;
+    let contents: Vec<_> = changeset
+        .path_operations
+        .iter()
+        .filter_map(|op| match op {
+            PathOperation::File(FileOperation::Create { content, .. }) => {
+                Some(String::from_utf8_lossy(content).to_string())
+            }
+            _ => None,
+        })
+        .collect();
+    assert_eq!(contents, vec!["server=10.0.0.2\n".to_string()]);
+
+    Ok(())
+}
@@ -0,0 +1,66 @@
+use bois::{
+    changeset::{Changeset, DirectoryOperation, FileOperation, PackageInstall, PathOperation},
+    handlers::{hooks::changeset_envs, packages::PackageManager},
+};
+use pretty_assertions::assert_eq;
+use testresult::TestResult;
+
+use crate::helper::config;
+
+/// Deploy hooks get a summary of the changeset via environment variables.
+#[test]
+fn hook_environment() -> TestResult {
+    let cache_dir = tempfile::tempdir()?;
+    let config = config(cache_dir.path());
+
+    let changeset = Changeset {
+        package_installs: vec![
+            PackageInstall {
+                manager: PackageManager::Pacman,
+                name: "neovim".into(),
+            },
+            PackageInstall {
+                manager: PackageManager::Pacman,
+                name: "ripgrep".into(),
+            },
+        ],
+        path_operations: vec![
+            PathOperation::Directory(DirectoryOperation::Create {
+                path: "/etc/ssh".into(),
+                mode: 0o755,
+                owner: "root".into(),
+                group: "root".into(),
+            }),
+            PathOperation::File(FileOperation::Create {
+                path: "/etc/ssh/sshd_config".into(),
+                content: Vec::new(),
+                mode: 0o644,
+                owner: "root".into(),
+                group: "root".into(),
+            }),
+            PathOperation::File(FileOperation::Modify {
+                path: "/etc/hosts".into(),
+                content: None,
+                mode: Some(0o644),
+                owner: None,
+                group: None,
+            }),
+            PathOperation::File(FileOperation::Delete {
+                path: "/etc/motd".into(),
+            }),
+        ],
+        ..Default::default()
+    };
+
+    let envs = changeset_envs(&config, &changeset, "pre_deploy");
+    assert_eq!(envs.len(), 7);
+    assert_eq!(envs["BOIS_HOOK"], "pre_deploy");
+    assert_eq!(envs["BOIS_HOST"], "potato");
+    assert_eq!(envs["BOIS_PACKAGE_INSTALLS"], "neovim ripgrep");
+    assert_eq!(envs["BOIS_PACKAGE_UNINSTALLS"], "");
+    assert_eq!(envs["BOIS_CREATED_PATHS"], "/etc/ssh\n/etc/ssh/sshd_config");
+    assert_eq!(envs["BOIS_MODIFIED_PATHS"], "/etc/hosts");
+    assert_eq!(envs["BOIS_DELETED_PATHS"], "/etc/motd");
+
+    Ok(())
+}
@@ -0,0 +1,122 @@
+use std::{fs, path::PathBuf};
+
+use bois::deployed_state::{CURRENT_VERSION, DeployedPath, DeployedState, hash_content};
+use pretty_assertions::assert_eq;
+use testresult::TestResult;
+
+use crate::helper::config;
+
+/// State files from before versioning contain the whole serialized source tree.
+/// Make sure they're migrated to the current manifest format.
+#[test]
+fn migrate_v1_state() -> TestResult {
+    let cache_dir = tempfile::tempdir()?;
+    fs::copy(
+        "tests/deployed_state/input/v1.yml",
+        cache_dir.path().join("deployed_state.yml"),
+    )?;
+    let config = config(cache_dir.path());
+
+    let state = DeployedState::read(&config)?.expect("State file should exist");
+    assert_eq!(state.version, CURRENT_VERSION);
+    assert_eq!(state.name, "potato");
+    assert!(state.packages.values().any(|pkgs| pkgs.contains("neovim")));
+
+    let paths: Vec<_> = state.paths.iter().map(|path| path.path()).collect();
+    assert_eq!(
+        paths,
+        vec![
+            PathBuf::from("/etc/lenovo_fix.conf"),
+            PathBuf::from("/etc/modprobe.d"),
+            PathBuf::from("/etc/modprobe.d/nobeep.conf"),
+        ]
+    );
+
+    let DeployedPath::File(file) = &state.paths[2] else {
+        panic!("Expected a file at {:?}", state.paths[2]);
+    };
+    assert_eq!(
+        file.origin,
+        PathBuf::from("groups/base/modprobe.d/nobeep.conf")
+    );
+    assert_eq!(file.hash, hash_content("blacklist pcspkr"));
+    assert_eq!(file.mode, Some(0o644));
+    assert_eq!(file.owner, Some("root".to_string()));
+
+    Ok(())
+}
+
+/// A saved manifest must be read back unchanged.
+#[test]
+fn roundtrip_current_state() -> TestResult {
+    let cache_dir = tempfile::tempdir()?;
+    fs::copy(
+        "tests/deployed_state/input/v1.yml",
+        cache_dir.path().join("deployed_state.yml"),
+    )?;
+    let config = config(cache_dir.path());
+
+    let state = DeployedState::read(&config)?.expect("State file should exist");
+    state.save(&config)?;
+    let saved = fs::read_to_string(cache_dir.path().join("deployed_state.yml"))?;
+    assert!(saved.starts_with(&format!("version: {CURRENT_VERSION}")));
+
+    let reread = DeployedState::read(&config)?.expect("State file should exist");
+    assert_eq!(state, reread);
+
+    Ok(())
+}
+
+/// Version 2 manifests don't know about create_only files, blocks or absent paths yet.
+#[test]
+fn migrate_v2_state() -> TestResult {
+    let cache_dir = tempfile::tempdir()?;
+    fs::copy(
+        "tests/deployed_state/input/v2.yml",
+        cache_dir.path().join("deployed_state.yml"),
+    )?;
+    let config = config(cache_dir.path());
+
+    let state = DeployedState::read(&config)?.expect("State file should exist");
+    assert_eq!(state.version, CURRENT_VERSION);
+    assert!(state.absent.is_empty());
+
+    let DeployedPath::File(file) = &state.paths[0] else {
+        panic!("Expected a file at {:?}", state.paths[0]);
+    };
+    assert_eq!(file.path, PathBuf::from("/etc/lenovo_fix.conf"));
+    assert!(!file.create_only);
+    assert_eq!(file.block, None);
+
+    Ok(())
+}
+
+/// Version 3 manifests always contain the metadata of block files, which isn't enforced.
+#[test]
+fn migrate_v3_state() -> TestResult {
+    let cache_dir = tempfile::tempdir()?;
+    fs::copy(
+        "tests/deployed_state/input/v3.yml",
+        cache_dir.path().join("deployed_state.yml"),
+    )?;
+    let config = config(cache_dir.path());
+
+    let state = DeployedState::read(&config)?.expect("State file should exist");
+    assert_eq!(state.version, CURRENT_VERSION);
+
+    let DeployedPath::File(file) = &state.paths[0] else {
+        panic!("Expected a file at {:?}", state.paths[0]);
+    };
+    assert_eq!(file.mode, Some(0o644));
+    assert_eq!(file.owner, Some("root".to_string()));
+
+    let DeployedPath::File(block) = &state.paths[1] else {
+        panic!("Expected a file at {:?}", state.paths[1]);
+    };
+    assert!(block.block.is_some());
+    assert_eq!(block.mode, None);
+    assert_eq!(block.owner, None);
+    assert_eq!(block.group, None);
+
+    Ok(())
+}
@@ -0,0 +1,101 @@
+use std::fs;
+
+use bois::templating::facts::gather_facts_from;
+use pretty_assertions::assert_eq;
+use testresult::TestResult;
+
+/// Facts are read from the files of the given root.
+#[test]
+fn gather_facts() -> TestResult {
+    let root = tempfile::tempdir()?;
+    let root = root.path();
+    fs::create_dir_all(root.join("etc"))?;
+    fs::create_dir_all(root.join("proc/sys/kernel"))?;
+    fs::create_dir_all(root.join("sys/class/net/lo"))?;
+    fs::create_dir_all(root.join("sys/class/net/wlan0/wireless"))?;
+    fs::create_dir_all(root.join("sys/class/power_supply/BAT0"))?;
+    fs::create_dir_all(root.join("sys/class/drm/card0/device"))?;
+    fs::create_dir_all(root.join("sys/class/drm/card0-DP-1/device"))?;
+
+    fs::write(
+        root.join("etc/os-release"),
+        "# comment\nNAME=\"Arch Linux\"\nID=arch\nBUILD_ID='rolling'\n",
+    )?;
+    fs::write(root.join("etc/machine-id"), "abc123\n")?;
+    fs::write(root.join("proc/sys/kernel/osrelease"), "6.9.1-arch1-1\n")?;
+    fs::write(
+        root.join("proc/cpuinfo"),
+        "processor\t: 0\nvendor_id\t: AuthenticAMD\nmodel name\t: AMD Ryzen 7\n\n\
+         processor\t: 1\nvendor_id\t: AuthenticAMD\nmodel name\t: AMD Ryzen 7\n",
+    )?;
+    fs::write(
+        root.join("proc/meminfo"),
+        "MemTotal:       16384000 kB\nMemFree:         1000 kB\n",
+    )?;
+    fs::write(
+        root.join("sys/class/net/wlan0/address"),
+        "aa:bb:cc:dd:ee:ff\n",
+    )?;
+    fs::write(root.join("sys/class/power_supply/BAT0/type"), "Battery\n")?;
+    fs::write(root.join("sys/class/drm/card0/device/vendor"), "0x1002\n")?;
+    fs::write(
+        root.join("sys/class/drm/card0-DP-1/device/vendor"),
+        "0x10de\n",
+    )?;
+
+    let facts = gather_facts_from(root);
+
+    assert_eq!(facts.os.get("name").map(String::as_str), Some("Arch Linux"));
+    assert_eq!(facts.os.get("id").map(String::as_str), Some("arch"));
+    assert_eq!(
+        facts.os.get("build_id").map(String::as_str),
+        Some("rolling")
+    );
+    assert_eq!(facts.machine_id.as_deref(), Some("abc123"));
+    assert_eq!(facts.kernel.release.as_deref(), Some("6.9.1-arch1-1"));
+    assert_eq!(facts.cpu.count, 2);
+    assert_eq!(facts.cpu.vendor.as_deref(), Some("amd"));
+    assert_eq!(facts.cpu.model.as_deref(), Some("AMD Ryzen 7"));
+    assert_eq!(facts.memory.total_mb, Some(16000));
+
+    let interfaces: Vec<_> = facts
+        .network
+        .iter()
+        .map(|interface| {
+            (
+                interface.name.as_str(),
+                interface.mac.as_deref(),
+                interface.wireless,
+            )
+        })
+        .collect();
+    assert_eq!(
+        interfaces,
+        vec![
+            ("lo", None, false),
+            ("wlan0", Some("aa:bb:cc:dd:ee:ff"), true)
+        ]
+    );
+
+    assert!(facts.has_battery);
+    assert_eq!(facts.gpus, vec!["amd".to_string()]);
+
+    Ok(())
+}
+
+/// Missing sources result in empty facts instead of errors.
+#[test]
+fn missing_facts() -> TestResult {
+    let root = tempfile::tempdir()?;
+    let facts = gather_facts_from(root.path());
+
+    assert!(facts.os.is_empty());
+    assert_eq!(facts.cpu.count, 0);
+    assert_eq!(facts.memory.total_mb, None);
+    assert!(facts.network.is_empty());
+    assert!(!facts.has_battery);
+    assert!(facts.gpus.is_empty());
+    assert_eq!(facts.machine_id, None);
+
+    Ok(())
+}
@@ -0,0 +1,180 @@
+use std::{collections::HashMap, fs};
+
+use bois::{
+    config::file::Undefined,
+    templating::{TemplateContext, render_template},
+};
+use pretty_assertions::assert_eq;
+use tempfile::TempDir;
+use testresult::TestResult;
+
+/// Render a template with some test variables.
+/// The trailing newline that's added by [render_template] is removed.
+fn render(content: &str) -> anyhow::Result<String> {
+    render_with_context(content, None)
+}
+
+/// Render a template with some test variables and the given context.
+fn render_with_context(content: &str, context: Option<&TemplateContext>) -> anyhow::Result<String> {
+    let vars = serde_yaml::from_str(
+        "host: laptop-potato\nconfig:\n  font:\n    size: 11\n    family: mono\n",
+    )?;
+    let rendered = render_template(content, &vars, &None, Undefined::Strict, context)?;
+
+    Ok(rendered.trim_end_matches('\n').to_string())
+}
+
+/// Create a bois directory that contains a `keys/id.pub` file and a `secret` file next to it.
+fn context() -> anyhow::Result<(TempDir, TemplateContext)> {
+    let root = tempfile::tempdir()?;
+    let bois_dir = root.path().join("bois");
+    fs::create_dir_all(bois_dir.join("keys"))?;
+    fs::write(bois_dir.join("keys/id.pub"), "ssh-ed25519 AAAA")?;
+    fs::write(root.path().join("secret"), "secret")?;
+
+    let context = TemplateContext {
+        bois_dir,
+        envs: HashMap::from([("BOIS_TEST_VAR".into(), "configured".into())]),
+        ..Default::default()
+    };
+
+    Ok((root, context))
+}
+
+#[test]
+fn to_json() -> TestResult {
+    assert_eq!(
+        render("{{ config.font | to_json }}")?,
+        "{\n  \"family\": \"mono\",\n  \"size\": 11\n}"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn to_yaml() -> TestResult {
+    assert_eq!(
+        render("{{ config | to_yaml }}")?,
+        "font:\n  family: mono\n  size: 11"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn to_toml() -> TestResult {
+    assert_eq!(
+        render("{{ config | to_toml }}")?,
+        "[font]\nfamily = \"mono\"\nsize = 11"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn to_ini() -> TestResult {
+    assert_eq!(
+        render("{{ config | to_ini }}")?,
+        "[font]\nfamily=mono\nsize=11"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn base64() -> TestResult {
+    assert_eq!(render("{{ 'secret' | b64encode }}")?, "c2VjcmV0");
+    assert_eq!(render("{{ 'c2VjcmV0' | b64decode }}")?, "secret");
+    assert!(render("{{ 'not base64!' | b64decode }}").is_err());
+
+    Ok(())
+}
+
+#[test]
+fn sha256() -> TestResult {
+    assert_eq!(
+        render("{{ 'bois' | sha256 }}")?,
+        "51eb76423aa25fb5b7c5d5e3b90535e3eccf110c6b7c611d7fd85c0b8e0a74d1"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn regex_replace() -> TestResult {
+    assert_eq!(
+        render("{{ 'eth0 eth1' | regex_replace('eth(\\\\d)', 'enp${1}s0') }}")?,
+        "enp0s0 enp1s0"
+    );
+    assert!(render("{{ 'eth0' | regex_replace('(', '') }}").is_err());
+
+    Ok(())
+}
+
+#[test]
+fn path_join() -> TestResult {
+    assert_eq!(
+        render("{{ '/etc' | path_join('nginx', 'nginx.conf') }}")?,
+        "/etc/nginx/nginx.conf"
+    );
+    assert_eq!(
+        render("{{ path_join('/home', host) }}")?,
+        "/home/laptop-potato"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn basename() -> TestResult {
+    assert_eq!(
+        render("{{ '/etc/nginx/nginx.conf' | basename }}")?,
+        "nginx.conf"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn env() -> TestResult {
+    let (_root, context) = context()?;
+    let render = |content| render_with_context(content, Some(&context));
+
+    assert_eq!(render("{{ env('BOIS_TEST_VAR') }}")?, "configured");
+    assert_eq!(render("{{ env('HOME') }}")?, std::env::var("HOME")?);
+    assert_eq!(
+        render("{{ env('BOIS_MISSING_VAR', 'fallback') }}")?,
+        "fallback"
+    );
+    assert!(render("{{ env('BOIS_MISSING_VAR') }}").is_err());
+
+    Ok(())
+}
+
+#[test]
+fn read_file() -> TestResult {
+    let (_root, context) = context()?;
+    let render = |content| render_with_context(content, Some(&context));
+
+    assert_eq!(
+        render("{{ read_file('keys/id.pub') }}")?,
+        "ssh-ed25519 AAAA"
+    );
+    // Files outside the bois directory can't be read.
+    assert!(render("{{ read_file('../secret') }}").is_err());
+    assert!(render("{{ read_file('missing') }}").is_err());
+    // Without a configuration, there's no bois directory to read from.
+    assert!(render_with_context("{{ read_file('keys/id.pub') }}", None).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn hostname_matches() -> TestResult {
+    assert_eq!(render("{{ hostname_matches('laptop-*') }}")?, "true");
+    assert_eq!(render("{{ hostname_matches('laptop-potat?') }}")?, "true");
+    assert_eq!(render("{{ hostname_matches('desktop-*') }}")?, "false");
+    assert_eq!(render("{{ hostname_matches('laptop') }}")?, "false");
+
+    Ok(())
+}
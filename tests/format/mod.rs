@@ -0,0 +1,93 @@
+use std::{fs, path::Path};
+
+use bois::state::{directory::read_directory, file::Entry, format::DataFormat};
+use pretty_assertions::assert_eq;
+use testresult::TestResult;
+
+const DATA: &str = "
+window:
+  opacity: 0.9
+  padding:
+    x: 4
+font:
+  size: 11
+";
+
+#[test]
+fn yaml_to_toml() -> TestResult {
+    let value = DataFormat::Yaml.parse(DATA)?;
+    let content = DataFormat::Toml.serialize(&value)?;
+
+    assert_eq!(
+        content,
+        "[window]\nopacity = 0.9\n\n[window.padding]\nx = 4\n\n[font]\nsize = 11\n"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn yaml_to_json() -> TestResult {
+    let value = DataFormat::Yaml.parse("editor:\n  tab_size: 4\n")?;
+    let content = DataFormat::Json.serialize(&value)?;
+
+    assert_eq!(content, "{\n  \"editor\": {\n    \"tab_size\": 4\n  }\n}\n");
+
+    Ok(())
+}
+
+/// Top-level scalars come first, every mapping becomes a section.
+#[test]
+fn yaml_to_ini() -> TestResult {
+    let value = DataFormat::Yaml.parse("name: potato\nLogin:\n  HandleLidSwitch: ignore\n")?;
+    let content = DataFormat::Ini.serialize(&value)?;
+
+    assert_eq!(content, "name=potato\n\n[Login]\nHandleLidSwitch=ignore\n");
+    assert_eq!(
+        DataFormat::Ini.parse(&content)?,
+        DataFormat::Yaml.parse("name: potato\nLogin:\n  HandleLidSwitch: ignore\n")?
+    );
+
+    Ok(())
+}
+
+/// Nested sections can't be represented in INI.
+#[test]
+fn ini_rejects_nesting() -> TestResult {
+    let value = DataFormat::Yaml.parse(DATA)?;
+    assert!(DataFormat::Ini.serialize(&value).is_err());
+
+    Ok(())
+}
+
+/// Data files are converted while reading and deployed with the new extension.
+#[test]
+fn read_data_file() -> TestResult {
+    let root = tempfile::tempdir()?;
+    let directory = root.path().join("alacritty");
+    fs::create_dir(&directory)?;
+    fs::write(
+        directory.join("alacritty.yml"),
+        "# bois_config\n# format: toml\n# bois_config\nfont:\n  size: 11\n",
+    )?;
+
+    let directory = read_directory(
+        root.path(),
+        Path::new("alacritty"),
+        None,
+        &serde_yaml::Value::Null,
+        None,
+    )?
+    .expect("Directory has no condition");
+    let Entry::File(file) = &directory.entries[0] else {
+        panic!("Expected a file");
+    };
+
+    assert_eq!(file.content, "[font]\nsize = 11\n");
+    assert_eq!(
+        file.file_path(Path::new("/home/potato/.config")),
+        Path::new("/home/potato/.config/alacritty/alacritty.toml")
+    );
+
+    Ok(())
+}
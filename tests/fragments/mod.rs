@@ -0,0 +1,125 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use bois::state::{
+    directory::{Directory, read_directory},
+    file::Entry,
+    fragments::assemble_fragments,
+};
+use pretty_assertions::assert_eq;
+use testresult::TestResult;
+
+/// Create a group directory with a single fragment directory.
+fn fragment_group(root: &Path, group: &str, directory: &str, files: &[(&str, &str)]) -> TestResult {
+    let path = root.join(group).join(directory);
+    fs::create_dir_all(&path)?;
+    fs::write(path.join("bois.yml"), "fragments: true\n")?;
+    for (name, content) in files {
+        fs::write(path.join(name), content)?;
+    }
+
+    Ok(())
+}
+
+/// Read a group directory the same way bois reads it.
+fn read_group(root: &Path, group: &str) -> TestResult<Directory> {
+    let mut directory = Directory::new(Path::new(""));
+    for entry in fs::read_dir(root.join(group))? {
+        let entry = entry?;
+        let relative_path = PathBuf::from(entry.file_name());
+        let sub_directory = read_directory(
+            &root.join(group),
+            &relative_path,
+            None,
+            &serde_yaml::Value::Null,
+            None,
+        )?
+        .expect("Directory has no condition");
+        directory.entries.push(Entry::Directory(sub_directory));
+    }
+
+    Ok(directory)
+}
+
+/// Get all files of a directory by their target path.
+fn files(directory: &Directory) -> Vec<(PathBuf, String)> {
+    directory
+        .entries
+        .iter()
+        .filter_map(|entry| match entry {
+            Entry::File(file) => Some((file.file_path(Path::new("/etc")), file.content.clone())),
+            Entry::Directory(_) => None,
+        })
+        .collect()
+}
+
+/// Fragments of several groups are concatenated in the order of their names.
+#[test]
+fn concatenate_fragments() -> TestResult {
+    let root = tempfile::tempdir()?;
+    fragment_group(
+        root.path(),
+        "base",
+        "hosts.d",
+        &[("10-base", "127.0.0.1 localhost\n")],
+    )?;
+    fragment_group(
+        root.path(),
+        "pc",
+        "hosts.d",
+        &[
+            ("05-pc", "# Managed by bois"),
+            ("20-pc", "192.168.1.10 nas\n"),
+        ],
+    )?;
+
+    let mut base = read_group(root.path(), "base")?;
+    let mut pc = read_group(root.path(), "pc")?;
+    assemble_fragments(Path::new("/etc"), &mut [&mut base, &mut pc])?;
+
+    assert_eq!(
+        files(&base),
+        vec![(
+            PathBuf::from("/etc/hosts"),
+            "# Managed by bois\n127.0.0.1 localhost\n192.168.1.10 nas\n".to_string()
+        )]
+    );
+    // The assembled file only exists once.
+    assert!(pc.entries.is_empty());
+
+    Ok(())
+}
+
+/// Fragments of structured files are merged.
+#[test]
+fn merge_fragments() -> TestResult {
+    let root = tempfile::tempdir()?;
+    fragment_group(
+        root.path(),
+        "base",
+        "settings.json.d",
+        &[("10-base", r#"{"editor": {"font": "mono", "size": 10}}"#)],
+    )?;
+    fragment_group(
+        root.path(),
+        "pc",
+        "settings.json.d",
+        &[("20-pc", r#"{"editor": {"size": 12}}"#)],
+    )?;
+
+    let mut base = read_group(root.path(), "base")?;
+    let mut pc = read_group(root.path(), "pc")?;
+    assemble_fragments(Path::new("/etc"), &mut [&mut base, &mut pc])?;
+
+    let files = files(&base);
+    assert_eq!(files[0].0, PathBuf::from("/etc/settings.json"));
+    let merged: serde_json::Value = serde_json::from_str(&files[0].1)?;
+    assert_eq!(
+        merged,
+        serde_json::json!({"editor": {"font": "mono", "size": 12}})
+    );
+
+    Ok(())
+}
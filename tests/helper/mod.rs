@@ -0,0 +1,83 @@
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use bois::{
+    changeset::{
+        Changeset,
+        DirectoryOperation,
+        FileOperation,
+        PathOperation,
+        state_to_host::create_changeset,
+    },
+    config::bois::{Configuration, Mode},
+    state::State,
+    system_state::SystemState,
+};
+
+/// Build a configuration whose cache and runtime directories point to the given directory.
+pub fn config(cache_dir: &Path) -> Configuration {
+    Configuration {
+        name: "potato".to_string(),
+        bois_dir: "/etc/bois".into(),
+        target_dir: "/etc".into(),
+        cache_dir: cache_dir.to_path_buf(),
+        runtime_dir: cache_dir.to_path_buf(),
+        envs: HashMap::new(),
+        mode: Mode::System,
+        secret_providers: Vec::new(),
+        overrides: Vec::new(),
+    }
+}
+
+/// Build a configuration for the bois directory at `root/bois`, which deploys to `root/target`.
+pub fn deploy_config(root: &Path) -> Configuration {
+    Configuration {
+        bois_dir: root.join("bois"),
+        target_dir: root.join("target"),
+        ..config(&root.join("cache"))
+    }
+}
+
+/// Write files relative to `root` and create their parent directories.
+pub fn write_files(root: &Path, files: &[(&str, &str)]) -> anyhow::Result<()> {
+    for (path, content) in files {
+        let path = root.join(path);
+        fs::create_dir_all(path.parent().unwrap())?;
+        fs::write(path, content)?;
+    }
+
+    Ok(())
+}
+
+/// Read the desired state of the bois directory and plan its deployment.
+pub fn plan(config: &Configuration) -> anyhow::Result<Changeset> {
+    let mut system_state = SystemState::new()?;
+    let state = State::new(config, &mut system_state)?;
+
+    create_changeset(config, &state, &mut system_state)
+}
+
+/// The kind and path of all path operations, e.g. `("create", "/etc/hosts")`.
+pub fn operations(changeset: &Changeset) -> Vec<(&'static str, PathBuf)> {
+    changeset
+        .path_operations
+        .iter()
+        .map(|op| match op {
+            PathOperation::File(FileOperation::Create { path, .. })
+            | PathOperation::Directory(DirectoryOperation::Create { path, .. }) => {
+                ("create", path.clone())
+            }
+            PathOperation::File(FileOperation::Modify { path, .. })
+            | PathOperation::Directory(DirectoryOperation::Modify { path, .. }) => {
+                ("modify", path.clone())
+            }
+            PathOperation::File(FileOperation::Delete { path })
+            | PathOperation::Directory(DirectoryOperation::Delete { path }) => {
+                ("delete", path.clone())
+            }
+        })
+        .collect()
+}
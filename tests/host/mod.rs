@@ -0,0 +1,37 @@
+use std::fs;
+
+use bois::{state::host::read_host, templating::TemplateContext};
+use pretty_assertions::assert_eq;
+use testresult::TestResult;
+
+/// Groups are only enabled if their condition is met.
+#[test]
+fn conditional_groups() -> TestResult {
+    let root = tempfile::tempdir()?;
+    let host_dir = root.path().join("hosts/potato");
+    fs::create_dir_all(&host_dir)?;
+    fs::write(
+        host_dir.join("host.yml"),
+        "
+groups:
+  - base
+  - name: laptop
+    when: has_battery
+  - { name: desktop, when: not has_battery }
+",
+    )?;
+    fs::write(host_dir.join("vars.yml"), "has_battery: true\n")?;
+
+    let context = TemplateContext {
+        bois_dir: root.path().to_path_buf(),
+        ..Default::default()
+    };
+    let host = read_host(&context, "potato", &[])?;
+    assert_eq!(host.group_names, vec!["base", "laptop"]);
+    assert_eq!(
+        host.variables["boi_groups"],
+        serde_yaml::from_str::<serde_yaml::Value>("[base, laptop]")?
+    );
+
+    Ok(())
+}
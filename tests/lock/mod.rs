@@ -0,0 +1,39 @@
+use bois::lock::DeployLock;
+use testresult::TestResult;
+
+use crate::helper::config;
+
+/// A second bois run must not be able to take the lock while the first one holds it.
+#[test]
+fn lock_contention() -> TestResult {
+    let cache_dir = tempfile::tempdir()?;
+    let config = config(cache_dir.path());
+
+    let lock = DeployLock::acquire(&config, false)?;
+    let Err(err) = DeployLock::acquire(&config, false) else {
+        panic!("Lock should be held by the first process");
+    };
+    assert!(
+        err.to_string()
+            .contains(&format!("pid: {}", std::process::id()))
+    );
+
+    // Once released, the lock can be taken again.
+    drop(lock);
+    DeployLock::acquire(&config, false)?;
+
+    Ok(())
+}
+
+/// The cache directory doesn't exist on a fresh system, so it has to be created.
+#[test]
+fn missing_cache_dir() -> TestResult {
+    let root = tempfile::tempdir()?;
+    let cache_dir = root.path().join("var/lib/bois");
+    let config = config(&cache_dir);
+
+    let _lock = DeployLock::acquire(&config, false)?;
+    assert!(cache_dir.join("bois.lock").is_file());
+
+    Ok(())
+}
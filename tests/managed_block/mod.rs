@@ -0,0 +1,178 @@
+use std::{fs, os::unix::fs::PermissionsExt};
+
+use bois::{
+    changeset::{
+        FileOperation,
+        PathOperation,
+        host_to_state,
+        managed_block::BlockMarkers,
+        state_to_host,
+    },
+    deployed_state::DeployedState,
+    handlers::paths::handle_path_operations,
+    state::State,
+    system_state::SystemState,
+};
+use pretty_assertions::assert_eq;
+use testresult::TestResult;
+
+use crate::helper::{deploy_config, plan, write_files};
+
+/// Content outside of an existing block must be left untouched.
+#[test]
+fn replace_existing_block() {
+    let markers = BlockMarkers::new("hosts", "#");
+    let content = "\
+127.0.0.1 localhost
+# BEGIN bois hosts
+192.168.1.10 old
+# END bois hosts
+::1 localhost
+";
+
+    let new_content = markers.replace(content, "192.168.1.10 nas\n192.168.1.11 printer");
+    assert_eq!(
+        new_content,
+        "\
+127.0.0.1 localhost
+# BEGIN bois hosts
+192.168.1.10 nas
+192.168.1.11 printer
+# END bois hosts
+::1 localhost
+"
+    );
+    assert_eq!(
+        markers.extract(&new_content),
+        Some("192.168.1.10 nas\n192.168.1.11 printer".to_string())
+    );
+}
+
+/// Files without the block get it appended at the end.
+#[test]
+fn append_missing_block() {
+    let markers = BlockMarkers::new("env", "//");
+    let content = "let x = 1;\n";
+
+    assert_eq!(markers.extract(content), None);
+    assert_eq!(
+        markers.replace(content, "let y = 2;"),
+        "let x = 1;\n// BEGIN bois env\nlet y = 2;\n// END bois env\n"
+    );
+}
+
+/// Blocks with other ids aren't touched.
+#[test]
+fn ignore_other_blocks() {
+    let markers = BlockMarkers::new("one", "#");
+    let content = "# BEGIN bois two\nfoo\n# END bois two\n";
+
+    assert_eq!(markers.extract(content), None);
+    assert_eq!(
+        markers.replace(content, "bar"),
+        "# BEGIN bois two\nfoo\n# END bois two\n# BEGIN bois one\nbar\n# END bois one\n"
+    );
+}
+
+/// Several files may manage different blocks inside the same file.
+/// All blocks are written in a single change, which doesn't touch the file's mode.
+#[test]
+fn multiple_blocks_in_one_file() -> TestResult {
+    let root = tempfile::tempdir()?;
+    let block = |id: &str| format!("# bois_config\n# block: {id}\n# bois_config\n");
+    write_files(
+        root.path(),
+        &[
+            ("bois/hosts/potato/host.yml", "groups: [base]\n"),
+            (
+                "bois/hosts/potato/hosts",
+                &format!("{}192.168.1.10 nas\n", block("nas")),
+            ),
+            (
+                "bois/groups/base/hosts",
+                &format!("{}192.168.1.11 printer\n", block("printer")),
+            ),
+            (
+                "target/hosts",
+                "127.0.0.1 localhost\n# BEGIN bois nas\n192.168.1.9 old\n# END bois nas\n",
+            ),
+        ],
+    )?;
+    let target = root.path().join("target/hosts");
+    fs::set_permissions(&target, fs::Permissions::from_mode(0o600))?;
+    let config = deploy_config(root.path());
+
+    let changeset = plan(&config)?;
+    let [
+        PathOperation::File(FileOperation::Modify {
+            path,
+            content: Some(content),
+            mode: None,
+            owner: None,
+            group: None,
+        }),
+    ] = changeset.path_operations.as_slice()
+    else {
+        panic!("Expected a single content change, got {changeset:#?}");
+    };
+    assert_eq!(path, &target);
+    assert_eq!(
+        String::from_utf8_lossy(content),
+        "\
+127.0.0.1 localhost
+# BEGIN bois nas
+192.168.1.10 nas
+# END bois nas
+# BEGIN bois printer
+192.168.1.11 printer
+# END bois printer
+"
+    );
+
+    // A whole file can't be deployed to the same path as a block.
+    write_files(
+        root.path(),
+        &[("bois/groups/base/hosts", "::1 localhost\n")],
+    )?;
+    assert!(plan(&config).is_err());
+
+    Ok(())
+}
+
+/// The metadata of a block file isn't recorded, unless it's set explicitly.
+/// Otherwise the next plan would report the file's own mode as drift.
+#[test]
+fn no_drift_after_deploy() -> TestResult {
+    let root = tempfile::tempdir()?;
+    write_files(
+        root.path(),
+        &[
+            ("bois/hosts/potato/host.yml", "groups: []\n"),
+            (
+                "bois/hosts/potato/hosts",
+                "# bois_config\n# block: nas\n# bois_config\n192.168.1.10 nas\n",
+            ),
+            ("target/hosts", "127.0.0.1 localhost\n"),
+        ],
+    )?;
+    let source = root.path().join("bois/hosts/potato/hosts");
+    fs::set_permissions(&source, fs::Permissions::from_mode(0o600))?;
+    let target = root.path().join("target/hosts");
+    fs::set_permissions(&target, fs::Permissions::from_mode(0o644))?;
+    let config = deploy_config(root.path());
+    fs::create_dir_all(&config.cache_dir)?;
+
+    // Deploy the block and save the manifest.
+    let mut system_state = SystemState::new()?;
+    let state = State::new(&config, &mut system_state)?;
+    let changeset = state_to_host::create_changeset(&config, &state, &mut system_state)?;
+    handle_path_operations(&mut system_state, &changeset.path_operations)?;
+    DeployedState::from_state(&state).save(&config)?;
+
+    let deployed = DeployedState::read(&config)?.expect("State file should exist");
+    let drift = host_to_state::create_changeset(&config, &mut system_state, &deployed, &state)?;
+    assert!(drift.path_operations.is_empty(), "{drift:#?}");
+    assert_eq!(fs::metadata(&target)?.permissions().mode() & 0o777, 0o644);
+
+    Ok(())
+}
@@ -0,0 +1,67 @@
+use std::path::PathBuf;
+
+use bois::state::patch::{apply_patch, check_conflicts};
+use pretty_assertions::assert_eq;
+use testresult::TestResult;
+
+const ORIGINAL: &str = "\
+[options]
+HoldPkg     = pacman glibc
+#Color
+ParallelDownloads = 5
+";
+
+const PATCH: &str = "\
+--- a/pacman.conf
++++ b/pacman.conf
+@@ -1,4 +1,4 @@
+ [options]
+ HoldPkg     = pacman glibc
+-#Color
++Color
+ ParallelDownloads = 5
+";
+
+/// A patch is applied on top of the original file.
+#[test]
+fn apply_clean_patch() -> TestResult {
+    let content = apply_patch(ORIGINAL, PATCH)?;
+    assert_eq!(
+        content,
+        "[options]\nHoldPkg     = pacman glibc\nColor\nParallelDownloads = 5\n"
+    );
+
+    Ok(())
+}
+
+/// If the original changed in the patched region, the patch must not be applied blindly.
+#[test]
+fn detect_conflict() {
+    let changed_original = ORIGINAL.replace("#Color", "#Color=auto");
+
+    let result = apply_patch(&changed_original, PATCH);
+    let err = result.expect_err("Patch shouldn't apply to a changed original");
+    assert!(format!("{err:#}").contains("Patch conflict"));
+}
+
+/// All conflicts are part of the returned error.
+#[test]
+fn report_conflicts() {
+    assert!(check_conflicts(&[]).is_ok());
+
+    let conflicts = vec![
+        (
+            PathBuf::from("/etc/pacman.conf"),
+            "Patch conflict".to_string(),
+        ),
+        (
+            PathBuf::from("/etc/makepkg.conf"),
+            "Couldn't parse patch".to_string(),
+        ),
+    ];
+    let err = check_conflicts(&conflicts).expect_err("Conflicts must be reported");
+    let message = format!("{err:#}");
+    assert!(message.contains("Found 2 patch conflicts"));
+    assert!(message.contains("\"/etc/pacman.conf\": Patch conflict"));
+    assert!(message.contains("\"/etc/makepkg.conf\": Couldn't parse patch"));
+}
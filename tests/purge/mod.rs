@@ -0,0 +1,66 @@
+use std::collections::HashSet;
+
+use bois::changeset::helper::unmanaged_entries;
+use pretty_assertions::assert_eq;
+use testresult::TestResult;
+
+use crate::helper::{deploy_config, operations, plan, write_files};
+
+/// All entries that aren't managed are returned, sorted by path.
+#[test]
+fn detect_unmanaged_entries() -> TestResult {
+    let root = tempfile::tempdir()?;
+    write_files(
+        root.path(),
+        &[("b.conf", ""), ("a.conf", ""), ("sub/c.conf", "")],
+    )?;
+
+    let managed = HashSet::from([root.path().join("a.conf")]);
+    assert_eq!(
+        unmanaged_entries(root.path(), &managed)?,
+        vec![root.path().join("b.conf"), root.path().join("sub")]
+    );
+
+    Ok(())
+}
+
+/// Unmanaged paths in purged directories are removed exactly once, even if the directory is
+/// declared by the host and a group.
+#[test]
+fn purge_unmanaged_paths() -> TestResult {
+    let root = tempfile::tempdir()?;
+    write_files(
+        root.path(),
+        &[
+            ("bois/hosts/potato/host.yml", "groups: [base]\n"),
+            ("bois/hosts/potato/app/bois.yml", "purge: true\n"),
+            ("bois/hosts/potato/app/a.conf", "a\n"),
+            ("bois/hosts/potato/other/c.conf", "c\n"),
+            ("bois/groups/base/app/bois.yml", "purge: true\n"),
+            ("bois/groups/base/app/b.conf", "b\n"),
+            ("target/app/a.conf", "a\n"),
+            ("target/app/b.conf", "b\n"),
+            ("target/app/stale.conf", "stale\n"),
+            ("target/app/old/x.conf", "x\n"),
+            ("target/other/c.conf", "c\n"),
+            ("target/other/unmanaged.conf", "unmanaged\n"),
+        ],
+    )?;
+    let config = deploy_config(root.path());
+
+    let app = root.path().join("target/app");
+    let changeset = plan(&config)?;
+    assert_eq!(
+        operations(&changeset),
+        vec![
+            ("delete", app.join("old/x.conf")),
+            ("delete", app.join("old")),
+            ("delete", app.join("stale.conf")),
+        ]
+    );
+
+    // Nothing has been touched during planning.
+    assert!(app.join("stale.conf").exists());
+
+    Ok(())
+}
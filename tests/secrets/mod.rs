@@ -0,0 +1,232 @@
+use std::{collections::HashMap, fs, os::unix::fs::PermissionsExt, path::Path};
+
+use bois::{
+    config::{bois::RawConfiguration, file::Undefined},
+    templating::{
+        TemplateContext,
+        password_managers::secret::{SecretProvider, SecretProviderKind},
+        render_template,
+    },
+};
+use pretty_assertions::assert_eq;
+use testresult::TestResult;
+
+/// Create a provider whose executable is a shell script with the given body.
+fn provider(dir: &Path, kind: SecretProviderKind, body: &str) -> TestResult<SecretProvider> {
+    let path = dir.join(format!("{kind:?}"));
+    fs::write(&path, format!("#!/bin/sh\n{body}\n"))?;
+    fs::set_permissions(&path, fs::Permissions::from_mode(0o755))?;
+
+    Ok(SecretProvider {
+        name: "stub".into(),
+        kind,
+        command: Some(path.to_string_lossy().to_string()),
+        args: Vec::new(),
+        env: HashMap::new(),
+    })
+}
+
+/// Each backend calls its executable with the expected arguments.
+#[test]
+fn backend_arguments() -> TestResult {
+    let dir = tempfile::tempdir()?;
+    let envs = HashMap::new();
+
+    let gopass = provider(dir.path(), SecretProviderKind::Gopass, "echo \"$*\"")?;
+    assert_eq!(
+        gopass.fetch("web/github", None, &envs)?,
+        "show --password web/github"
+    );
+    assert_eq!(
+        gopass.fetch("web/github", Some("user"), &envs)?,
+        "show web/github user"
+    );
+
+    let rbw = provider(dir.path(), SecretProviderKind::Rbw, "echo \"$*\"")?;
+    assert_eq!(rbw.fetch("github.com", None, &envs)?, "get github.com");
+    assert_eq!(
+        rbw.fetch("github.com", Some("username"), &envs)?,
+        "get --field username github.com"
+    );
+
+    let secret_tool = provider(dir.path(), SecretProviderKind::SecretTool, "echo \"$*\"")?;
+    assert_eq!(
+        secret_tool.fetch("service=github user=nuke", None, &envs)?,
+        "lookup service github user nuke"
+    );
+    assert!(secret_tool.fetch("github", None, &envs).is_err());
+
+    let mut command = provider(dir.path(), SecretProviderKind::Command, "echo \"$*\"")?;
+    command.args = vec!["read".into()];
+    assert_eq!(
+        command.fetch("op://vault/item", None, &envs)?,
+        "read op://vault/item"
+    );
+
+    Ok(())
+}
+
+/// Fields are parsed from yaml for backends that don't support them.
+#[test]
+fn yaml_fields() -> TestResult {
+    let dir = tempfile::tempdir()?;
+    let envs = HashMap::new();
+
+    let pass = provider(
+        dir.path(),
+        SecretProviderKind::Pass,
+        "printf 'hunter2\\n===\\nuser: nuke\\nport: 22\\n'",
+    )?;
+    assert_eq!(pass.fetch("ssh", None, &envs)?, "hunter2");
+    assert_eq!(pass.fetch("ssh", Some("user"), &envs)?, "nuke");
+    assert_eq!(pass.fetch("ssh", Some("port"), &envs)?, "22");
+    assert!(pass.fetch("ssh", Some("missing"), &envs).is_err());
+
+    let command = provider(
+        dir.path(),
+        SecretProviderKind::Command,
+        "printf 'user: nuke\\ntoken: abc\\n'",
+    )?;
+    assert_eq!(command.fetch("api", Some("token"), &envs)?, "abc");
+
+    Ok(())
+}
+
+/// Provider environment variables take precedence over the global ones.
+#[test]
+fn environment() -> TestResult {
+    let dir = tempfile::tempdir()?;
+    let mut command = provider(
+        dir.path(),
+        SecretProviderKind::Command,
+        "echo \"$BOIS_SECRET_STORE $BOIS_SECRET_SESSION\"",
+    )?;
+    command.env = HashMap::from([("BOIS_SECRET_SESSION".into(), "provider".into())]);
+
+    let envs = HashMap::from([
+        ("BOIS_SECRET_STORE".to_string(), "global".to_string()),
+        ("BOIS_SECRET_SESSION".to_string(), "global".to_string()),
+    ]);
+    assert_eq!(command.fetch("key", None, &envs)?, "global provider");
+
+    Ok(())
+}
+
+/// Failing commands are reported as errors.
+#[test]
+fn failing_command() -> TestResult {
+    let dir = tempfile::tempdir()?;
+    let command = provider(dir.path(), SecretProviderKind::Command, "exit 1")?;
+    assert!(command.fetch("key", None, &HashMap::new()).is_err());
+
+    Ok(())
+}
+
+/// Provider names must be unique and `command` providers need a command.
+#[test]
+fn provider_config() -> TestResult {
+    let dir = tempfile::tempdir()?;
+    let dir = dir.path().to_string_lossy();
+    let base = format!(
+        "name: potato\nbois_dir: {dir}\ntarget_dir: {dir}\ncache_dir: {dir}\nruntime_dir: {dir}\n"
+    );
+
+    let config: RawConfiguration = serde_yaml::from_str(&format!(
+        "{base}secret_providers:\n  - name: keyring\n    kind: secret-tool\n  - name: op\n    kind: \
+         command\n    command: op\n    args: [read]\n"
+    ))?;
+    let config = config.build_configuration()?;
+    assert_eq!(config.secret_providers.len(), 2);
+    assert_eq!(
+        config.secret_providers[0].kind,
+        SecretProviderKind::SecretTool
+    );
+
+    let duplicates: RawConfiguration = serde_yaml::from_str(&format!(
+        "{base}secret_providers:\n  - name: work\n    kind: gopass\n  - name: work\n    kind: rbw\n"
+    ))?;
+    assert!(duplicates.build_configuration().is_err());
+
+    let no_command: RawConfiguration = serde_yaml::from_str(&format!(
+        "{base}secret_providers:\n  - name: custom\n    kind: command\n"
+    ))?;
+    assert!(no_command.build_configuration().is_err());
+
+    Ok(())
+}
+
+/// Render a template with the given secret providers.
+fn render(template: &str, secret_providers: Vec<SecretProvider>) -> anyhow::Result<String> {
+    let context = TemplateContext {
+        envs: HashMap::from([("BOIS_SECRET_STORE".into(), "global".into())]),
+        secret_providers,
+        ..Default::default()
+    };
+
+    render_template(
+        template,
+        &serde_yaml::Value::Null,
+        &None,
+        Undefined::Strict,
+        Some(&context),
+    )
+}
+
+/// Templates get secrets from the providers of the context.
+#[test]
+fn secret_function() -> TestResult {
+    let dir = tempfile::tempdir()?;
+    let command = provider(
+        dir.path(),
+        SecretProviderKind::Command,
+        "printf \"$BOIS_SECRET_STORE $*\"",
+    )?;
+    assert_eq!(
+        render("{{ secret('stub', 'api') }}", vec![command])?,
+        "global api\n"
+    );
+
+    let pass = provider(
+        dir.path(),
+        SecretProviderKind::Pass,
+        "printf 'hunter2\\n===\\nuser: nuke\\n'",
+    )?;
+    assert_eq!(
+        render(
+            "{{ secret('stub', 'ssh') }} {{ secret('stub', 'ssh', field='user') }}",
+            vec![pass]
+        )?,
+        "hunter2 nuke\n"
+    );
+
+    Ok(())
+}
+
+/// Unknown providers and unknown arguments fail the template.
+#[test]
+fn secret_function_errors() -> TestResult {
+    let dir = tempfile::tempdir()?;
+    let command = provider(dir.path(), SecretProviderKind::Command, "echo secret")?;
+
+    let err = render("{{ secret('work', 'api') }}", vec![command.clone()])
+        .expect_err("Unknown provider should fail");
+    assert!(
+        format!("{err:#}").contains("Found no secret provider with name 'work'"),
+        "{err:#}"
+    );
+    assert!(render("{{ secret('stub', 'api', fields='user') }}", vec![command]).is_err());
+
+    // Secrets are only available with a context.
+    assert!(
+        render_template(
+            "{{ secret('stub', 'api') }}",
+            &serde_yaml::Value::Null,
+            &None,
+            Undefined::Strict,
+            None,
+        )
+        .is_err()
+    );
+
+    Ok(())
+}
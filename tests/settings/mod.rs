@@ -0,0 +1,140 @@
+use bois::changeset::settings::Settings;
+use pretty_assertions::assert_eq;
+use serde_yaml::Mapping;
+use testresult::TestResult;
+
+use crate::helper::{deploy_config, operations, plan, write_files};
+
+fn settings(yaml: &str) -> TestResult<Settings> {
+    let mapping: Mapping = serde_yaml::from_str(yaml)?;
+    Ok(Settings::from_yaml(&mapping)?)
+}
+
+/// Keys inside of sections are set in place, while comments and ordering are kept.
+#[test]
+fn set_section_keys() -> TestResult {
+    let settings = settings(
+        "
+Login:
+  HandleLidSwitch: ignore
+  KillUserProcesses: no
+  HandlePowerKey: null
+",
+    )?;
+    let content = "\
+# See logind.conf(5) for details.
+[Login]
+#HandleLidSwitch=suspend
+HandleLidSwitch=suspend
+HandlePowerKey=poweroff
+
+[Other]
+Key=value
+";
+
+    assert_eq!(
+        settings.apply(content),
+        "\
+# See logind.conf(5) for details.
+[Login]
+#HandleLidSwitch=suspend
+HandleLidSwitch=ignore
+KillUserProcesses=no
+
+[Other]
+Key=value
+"
+    );
+
+    Ok(())
+}
+
+/// Flat files keep their separator style, even for new keys.
+#[test]
+fn set_flat_keys() -> TestResult {
+    let settings = settings(
+        "
+vm.swappiness: 10
+kernel.sysrq: 1
+",
+    )?;
+    let content = "\
+# Swap less
+vm.swappiness = 60
+";
+
+    assert_eq!(
+        settings.apply(content),
+        "# Swap less\nvm.swappiness = 10\nkernel.sysrq = 1\n"
+    );
+
+    Ok(())
+}
+
+/// Sections that don't exist yet are appended to the end of the file.
+#[test]
+fn add_missing_section() -> TestResult {
+    let settings = settings(
+        "
+Journal:
+  SystemMaxUse: 500M
+",
+    )?;
+
+    assert_eq!(
+        settings.apply("[Other]\nKey=value\n"),
+        "[Other]\nKey=value\n\n[Journal]\nSystemMaxUse=500M\n"
+    );
+
+    Ok(())
+}
+
+/// Files with settings are managed, so purged directories keep them.
+#[test]
+fn keep_settings_in_purged_directory() -> TestResult {
+    let root = tempfile::tempdir()?;
+    write_files(
+        root.path(),
+        &[
+            (
+                "bois/hosts/potato/host.yml",
+                "groups: []\nsettings:\n  app/app.conf:\n    key: value\n",
+            ),
+            ("bois/hosts/potato/app/bois.yml", "purge: true\n"),
+            ("target/app/app.conf", "key=value\n"),
+            ("target/app/stale.conf", "stale\n"),
+        ],
+    )?;
+    let config = deploy_config(root.path());
+
+    let changeset = plan(&config)?;
+    assert_eq!(
+        operations(&changeset),
+        vec![("delete", root.path().join("target/app/stale.conf"))]
+    );
+
+    Ok(())
+}
+
+/// A file can't be deployed as a whole and be edited via settings.
+#[test]
+fn settings_collide_with_file() -> TestResult {
+    let root = tempfile::tempdir()?;
+    write_files(
+        root.path(),
+        &[
+            ("bois/hosts/potato/host.yml", "groups: [base]\n"),
+            ("bois/hosts/potato/app.conf", "key=value\n"),
+            (
+                "bois/groups/base/group.yml",
+                "settings:\n  app.conf:\n    key: other\n",
+            ),
+        ],
+    )?;
+    let config = deploy_config(root.path());
+
+    let err = plan(&config).expect_err("Settings must collide with the file");
+    assert!(format!("{err:#}").contains("has settings in"));
+
+    Ok(())
+}
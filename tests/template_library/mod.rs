@@ -0,0 +1,90 @@
+use std::fs;
+
+use bois::{
+    config::file::{Delimiters, Undefined},
+    templating::{TEMPLATES_DIR, TemplateContext, render_template},
+};
+use pretty_assertions::assert_eq;
+use tempfile::TempDir;
+use testresult::TestResult;
+
+/// Create a bois directory with an empty templates directory.
+fn context() -> TestResult<(TempDir, TemplateContext)> {
+    let root = tempfile::tempdir()?;
+    fs::create_dir(root.path().join(TEMPLATES_DIR))?;
+    let context = TemplateContext {
+        bois_dir: root.path().to_path_buf(),
+        ..Default::default()
+    };
+
+    Ok((root, context))
+}
+
+/// Templates can include and import shared templates from the templates directory.
+#[test]
+fn include_and_import() -> TestResult {
+    let (_root, context) = context()?;
+    let templates = context.bois_dir.join(TEMPLATES_DIR);
+    fs::create_dir(templates.join("network"))?;
+    fs::write(templates.join("header"), "# Managed by bois on {{ host }}")?;
+    fs::write(
+        templates.join("network/macros"),
+        "{% macro network(dns) %}[Network]\nDNS={{ dns }}\n{% endmacro %}",
+    )?;
+
+    let content = "{% include \"header\" %}\n\n\
+                   {% import \"network/macros\" as macros %}\
+                   {{ macros.network(\"1.1.1.1\") }}";
+    let vars = serde_yaml::from_str("host: potato")?;
+    let rendered = render_template(content, &vars, &None, Undefined::Strict, Some(&context))?;
+
+    assert_eq!(
+        rendered,
+        "# Managed by bois on potato\n[Network]\nDNS=1.1.1.1\n\n"
+    );
+
+    Ok(())
+}
+
+/// Included templates use the custom delimiters of the including file.
+#[test]
+fn include_with_custom_delimiters() -> TestResult {
+    let (_root, context) = context()?;
+    let templates = context.bois_dir.join(TEMPLATES_DIR);
+    fs::write(templates.join("header"), "# Host: #{{ host }}")?;
+
+    let delimiters: Delimiters = serde_yaml::from_str(
+        "block: ['#{%', '%}']\nvariable: ['#{{', '}}']\ncomment: ['#{#', '#}']",
+    )?;
+    let content = "#{% include \"header\" %}\n\n{{ not_a_variable }}";
+    let vars = serde_yaml::from_str("host: potato")?;
+    let rendered = render_template(
+        content,
+        &vars,
+        &Some(delimiters),
+        Undefined::Strict,
+        Some(&context),
+    )?;
+
+    assert_eq!(rendered, "# Host: potato\n{{ not_a_variable }}\n");
+
+    Ok(())
+}
+
+/// Including a missing template fails.
+#[test]
+fn missing_template() -> TestResult {
+    let (_root, context) = context()?;
+
+    let content = "{% include \"missing\" %}";
+    let result = render_template(
+        content,
+        &serde_yaml::Value::Null,
+        &None,
+        Undefined::Strict,
+        Some(&context),
+    );
+    assert!(result.is_err());
+
+    Ok(())
+}
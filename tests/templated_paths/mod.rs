@@ -0,0 +1,92 @@
+use std::{fs, path::Path};
+
+use bois::{
+    state::{directory::read_directory, file::Entry},
+    templating::render_path,
+};
+use pretty_assertions::assert_eq;
+use testresult::TestResult;
+
+/// File names, `path` and `rename` are rendered with the template variables.
+#[test]
+fn render_paths() -> TestResult {
+    let root = tempfile::tempdir()?;
+    let directory = root.path().join("config");
+    fs::create_dir(&directory)?;
+    fs::write(directory.join("{{ host }}.conf"), "content")?;
+    fs::write(
+        directory.join("other"),
+        "# bois_config\n# path: /home/{{ user }}/.config/app/config\n# rename: '{{ host }}.toml'\n# bois_config\ncontent",
+    )?;
+
+    let vars = serde_yaml::from_str("host: potato\nuser: nuke")?;
+    let directory = read_directory(root.path(), Path::new("config"), None, &vars, None)?
+        .expect("Directory has no condition");
+
+    let mut paths: Vec<_> = directory
+        .entries
+        .iter()
+        .map(|entry| match entry {
+            Entry::File(file) => file.file_path(Path::new("/etc")),
+            Entry::Directory(dir) => dir.file_path(Path::new("/etc")),
+        })
+        .collect();
+    paths.sort();
+
+    assert_eq!(
+        paths,
+        vec![
+            Path::new("/etc/config/potato.conf"),
+            Path::new("/home/nuke/.config/app/potato.toml"),
+        ]
+    );
+
+    Ok(())
+}
+
+/// Templated directory names are used for all of their children.
+#[test]
+fn render_directory_names() -> TestResult {
+    let root = tempfile::tempdir()?;
+    let directory = root.path().join("{{ host }}.d");
+    fs::create_dir(&directory)?;
+    fs::write(directory.join("file"), "content")?;
+
+    // Read the parent, so the directory itself is read via its entry.
+    fs::create_dir(root.path().join("parent"))?;
+    fs::rename(&directory, root.path().join("parent/{{ host }}.d"))?;
+
+    let vars = serde_yaml::from_str("host: potato")?;
+    let directory = read_directory(root.path(), Path::new("parent"), None, &vars, None)?
+        .expect("Directory has no condition");
+
+    let Entry::Directory(dir) = &directory.entries[0] else {
+        panic!("Expected a directory");
+    };
+    assert_eq!(
+        dir.file_path(Path::new("/etc")),
+        Path::new("/etc/parent/potato.d")
+    );
+    let Entry::File(file) = &dir.entries[0] else {
+        panic!("Expected a file");
+    };
+    assert_eq!(
+        file.file_path(Path::new("/etc")),
+        Path::new("/etc/parent/potato.d/file")
+    );
+
+    Ok(())
+}
+
+/// Paths with undefined variables fail to render instead of rendering to an empty string.
+#[test]
+fn undefined_path_variables() -> TestResult {
+    let vars = serde_yaml::from_str("host: potato")?;
+    assert_eq!(render_path("{{ host }}.conf", &vars, None)?, "potato.conf");
+
+    let err = render_path("/home/{{ user }}/.config", &vars, None)
+        .expect_err("Undefined variables must fail");
+    assert!(format!("{err:#}").contains("Failed to render path"));
+
+    Ok(())
+}
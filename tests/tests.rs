@@ -1 +1,25 @@
+pub mod absent;
+pub mod change_hooks;
+pub mod conditions;
+pub mod create_only;
+pub mod cross_host;
+pub mod deploy_hooks;
+pub mod deployed_state;
+pub mod facts;
 pub mod file_parser;
+pub mod filters;
+pub mod format;
+pub mod fragments;
+pub mod helper;
+pub mod host;
+pub mod lock;
+pub mod managed_block;
+pub mod patch;
+pub mod purge;
+pub mod secrets;
+pub mod settings;
+pub mod template_library;
+pub mod templated_paths;
+pub mod undefined;
+pub mod validation;
+pub mod variables;
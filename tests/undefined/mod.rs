@@ -0,0 +1,115 @@
+use std::{collections::BTreeMap, fs, path::Path};
+
+use bois::{
+    config::file::Undefined,
+    state::directory::read_directory,
+    templating::{
+        lint::{VariableUsage, lint_template},
+        render_template,
+    },
+};
+use pretty_assertions::assert_eq;
+use testresult::TestResult;
+
+/// Undefined variables fail to render by default, but may be allowed per file.
+#[test]
+fn strict_undefined() -> TestResult {
+    let vars = serde_yaml::from_str("machine:\n  threads: 16")?;
+
+    let result = render_template(
+        "{{ machine.thread }}",
+        &vars,
+        &None,
+        Undefined::Strict,
+        None,
+    );
+    assert!(result.is_err());
+
+    let rendered = render_template(
+        "threads={{ machine.thread }}",
+        &vars,
+        &None,
+        Undefined::Lenient,
+        None,
+    )?;
+    assert_eq!(rendered, "threads=\n");
+
+    // Optional variables can still be checked explicitly.
+    let rendered = render_template(
+        "{% if optional is defined %}{{ optional }}{% endif %}{{ machine.threads }}",
+        &vars,
+        &None,
+        Undefined::Strict,
+        None,
+    )?;
+    assert_eq!(rendered, "16\n");
+
+    Ok(())
+}
+
+/// The `undefined` option of the bois config opts into lenient mode.
+#[test]
+fn lenient_file() -> TestResult {
+    let root = tempfile::tempdir()?;
+    let directory = root.path().join("config");
+    fs::create_dir(&directory)?;
+    fs::write(
+        directory.join("lenient"),
+        "# bois_config\n# template: true\n# undefined: lenient\n# bois_config\n{{ missing }}",
+    )?;
+
+    let vars = serde_yaml::from_str("host: potato")?;
+    assert!(read_directory(root.path(), Path::new("config"), None, &vars, None).is_ok());
+
+    fs::write(
+        directory.join("strict"),
+        "# bois_config\n# template: true\n# bois_config\n{{ missing }}",
+    )?;
+    assert!(read_directory(root.path(), Path::new("config"), None, &vars, None).is_err());
+
+    Ok(())
+}
+
+/// All referenced variables are listed with their source.
+#[test]
+fn lint() -> TestResult {
+    let vars = serde_yaml::from_str("host: potato\nmachine:\n  threads: 16")?;
+    let sources = BTreeMap::from([
+        ("host".to_string(), "built-in".to_string()),
+        ("machine".to_string(), "hosts/potato/vars.yml".to_string()),
+    ]);
+
+    let content = "{{ host }} {{ machine.threads }} {{ machine.thread }} {{ typo }}\n\
+                   {% for item in items %}{{ item }}{% endfor %}";
+    let usages = lint_template(content, &None, &vars, &sources)?;
+
+    let usage = |name: &str, source: Option<&str>| VariableUsage {
+        name: name.to_string(),
+        source: source.map(ToString::to_string),
+    };
+    assert_eq!(
+        usages,
+        vec![
+            usage("host", Some("built-in")),
+            usage("items", None),
+            usage("machine.thread", None),
+            usage("machine.threads", Some("hosts/potato/vars.yml")),
+            usage("typo", None),
+        ]
+    );
+
+    Ok(())
+}
+
+/// Builtin and bois functions aren't reported as variables.
+#[test]
+fn lint_functions() -> TestResult {
+    let vars = serde_yaml::from_str("host: potato")?;
+    let content = "{{ env(\"HOME\") }} {{ range(3) | join(\",\") }}\n\
+                   {% set ns = namespace(count=0) %}{{ path_join(\"/etc\", \"bois\") }}\n\
+                   {{ hostname_matches(\"pot*\") }} {{ read_file(\"keys\") }} {{ secret(\"work\", \"key\") }}";
+    let usages = lint_template(content, &None, &vars, &BTreeMap::new())?;
+    assert_eq!(usages, Vec::new());
+
+    Ok(())
+}
@@ -0,0 +1,39 @@
+use std::path::PathBuf;
+
+use bois::{changeset::FileValidation, handlers::paths::validate_files};
+use testresult::TestResult;
+
+use crate::helper::config;
+
+fn validation(command: &str, content: &str) -> FileValidation {
+    FileValidation {
+        path: PathBuf::from("/etc/sudoers"),
+        command: command.to_string(),
+        content: content.as_bytes().to_vec(),
+    }
+}
+
+/// The validation command must see the new content and its failure must abort.
+#[test]
+fn validate_new_content() -> TestResult {
+    let runtime_dir = tempfile::tempdir()?;
+    let config = config(runtime_dir.path());
+
+    validate_files(
+        &config,
+        &[validation("grep -q 'root ALL' %s", "root ALL=(ALL) ALL")],
+    )?;
+    // Without `%s`, the path is appended to the command.
+    validate_files(
+        &config,
+        &[validation("grep -q 'root ALL'", "root ALL=(ALL) ALL")],
+    )?;
+
+    let result = validate_files(&config, &[validation("grep -q 'root ALL' %s", "typo")]);
+    assert!(result.is_err(), "Validation of invalid content should fail");
+
+    // No temporary files must be left behind.
+    assert_eq!(std::fs::read_dir(runtime_dir.path())?.count(), 0);
+
+    Ok(())
+}
@@ -0,0 +1,358 @@
+use std::fs;
+
+use bois::{
+    changeset::{FileOperation, PathOperation},
+    templating::variables::{
+        BUILTIN_SOURCE,
+        FACTS_SOURCE,
+        VariableScope,
+        annotate_sources,
+        env_override_layers,
+        file_variable_layers,
+        get_host_vars,
+        group_variable_layers,
+        group_variables,
+        host_variable_layers,
+        merge_layers,
+        override_layers,
+        parse_override,
+        variable_sources,
+    },
+};
+use pretty_assertions::assert_eq;
+use serde_yaml::Value;
+use testresult::TestResult;
+
+use crate::helper::{deploy_config, plan, write_files};
+
+/// Create a bois directory with global and host variables.
+fn bois_dir() -> TestResult<tempfile::TempDir> {
+    let root = tempfile::tempdir()?;
+    let host_dir = root.path().join("hosts/potato");
+    fs::create_dir_all(root.path().join("vars.d"))?;
+    fs::create_dir_all(host_dir.join("vars.d"))?;
+
+    fs::write(
+        root.path().join("vars.yml"),
+        "dns: 1.1.1.1\nmachine:\n  arch: x86_64\n  threads: 4\n",
+    )?;
+    fs::write(root.path().join("vars.d/20-user.yml"), "user: nuke\n")?;
+    fs::write(root.path().join("vars.d/10-dns.yaml"), "dns: 9.9.9.9\n")?;
+    fs::write(root.path().join("vars.d/README"), "not yaml")?;
+    fs::write(host_dir.join("vars.yml"), "machine:\n  threads: 16\n")?;
+    fs::write(host_dir.join("vars.d/secrets.yml"), "user: potato\n")?;
+
+    Ok(root)
+}
+
+/// Global, `vars.d` and host variables are deep-merged in order.
+#[test]
+fn merge_order() -> TestResult {
+    let root = bois_dir()?;
+    let variables = get_host_vars(root.path(), "potato", &[])?;
+
+    let expected: Value = serde_yaml::from_str(
+        "dns: 9.9.9.9\nmachine:\n  arch: x86_64\n  threads: 16\nuser: potato\n",
+    )?;
+    for key in ["dns", "machine", "user"] {
+        assert_eq!(variables[key], expected[key], "Variable {key}");
+    }
+    assert_eq!(variables["host"], Value::from("potato"));
+
+    Ok(())
+}
+
+/// The layers are ordered by precedence and every variable knows its source.
+#[test]
+fn sources() -> TestResult {
+    let root = bois_dir()?;
+    let root_path = root.path().to_string_lossy();
+
+    let layers = file_variable_layers(root.path(), "potato")?;
+    let layer_sources: Vec<_> = layers.iter().map(|layer| layer.source.clone()).collect();
+    assert_eq!(
+        layer_sources,
+        vec![
+            format!("{root_path}/vars.yml"),
+            format!("{root_path}/vars.d/10-dns.yaml"),
+            format!("{root_path}/vars.d/20-user.yml"),
+            format!("{root_path}/hosts/potato/vars.yml"),
+            format!("{root_path}/hosts/potato/vars.d/secrets.yml"),
+        ]
+    );
+
+    let sources = variable_sources(&host_variable_layers(root.path(), "potato", &[])?);
+    assert_eq!(sources["dns"], format!("{root_path}/vars.d/10-dns.yaml"));
+    assert_eq!(sources["machine.arch"], format!("{root_path}/vars.yml"));
+    assert_eq!(
+        sources["machine.threads"],
+        format!("{root_path}/hosts/potato/vars.yml")
+    );
+    assert_eq!(
+        sources["user"],
+        format!("{root_path}/hosts/potato/vars.d/secrets.yml")
+    );
+    assert_eq!(sources["host"], BUILTIN_SOURCE);
+
+    Ok(())
+}
+
+/// Vars files must contain a mapping.
+#[test]
+fn invalid_vars() -> TestResult {
+    let root = tempfile::tempdir()?;
+    fs::create_dir_all(root.path().join("hosts/potato"))?;
+    fs::write(root.path().join("vars.yml"), "- not a map\n")?;
+
+    assert!(get_host_vars(root.path(), "potato", &[]).is_err());
+
+    Ok(())
+}
+
+/// Overrides support dotted keys and yaml scalars.
+#[test]
+fn parse_overrides() -> TestResult {
+    let expected: serde_yaml::Mapping = serde_yaml::from_str("machine:\n  threads: 16\n")?;
+    assert_eq!(parse_override("machine.threads", "16")?, expected);
+
+    let expected: serde_yaml::Mapping = serde_yaml::from_str("is_laptop: true\n")?;
+    assert_eq!(parse_override("is_laptop", "true")?, expected);
+
+    // Everything that isn't a scalar is kept as a string.
+    let expected: serde_yaml::Mapping = serde_yaml::from_str("dns: '[1.1.1.1]'\n")?;
+    assert_eq!(parse_override("dns", "[1.1.1.1]")?, expected);
+    let expected: serde_yaml::Mapping = serde_yaml::from_str("name: ''\n")?;
+    assert_eq!(parse_override("name", "")?, expected);
+
+    assert!(parse_override("", "value").is_err());
+    assert!(parse_override("machine..threads", "16").is_err());
+    assert!(override_layers(&["no_value".to_string()], &[]).is_err());
+
+    Ok(())
+}
+
+/// `BOIS_VAR_*` environment variables use `__` for nested keys.
+#[test]
+fn env_overrides() -> TestResult {
+    let envs = vec![
+        ("BOIS_VAR_machine__threads".to_string(), "32".to_string()),
+        ("BOIS_VAR_is_laptop".to_string(), "false".to_string()),
+        ("BOIS_VAR_".to_string(), "ignored".to_string()),
+        ("PATH".to_string(), "/usr/bin".to_string()),
+    ];
+    let layers = env_override_layers(envs)?;
+
+    let sources: Vec<_> = layers.iter().map(|layer| layer.source.as_str()).collect();
+    assert_eq!(
+        sources,
+        vec!["$BOIS_VAR_is_laptop", "$BOIS_VAR_machine__threads"]
+    );
+    let expected: serde_yaml::Mapping = serde_yaml::from_str("machine:\n  threads: 32\n")?;
+    assert_eq!(layers[1].variables, expected);
+
+    Ok(())
+}
+
+/// Overrides take precedence over all other variables and are deep-merged.
+#[test]
+fn override_precedence() -> TestResult {
+    let root = bois_dir()?;
+    let vars_file = root.path().join("overrides.yml");
+    fs::write(&vars_file, "user: file\nmachine:\n  threads: 8\n")?;
+
+    let overrides = override_layers(
+        &["machine.threads=64".to_string(), "host=other".to_string()],
+        &[vars_file],
+    )?;
+    let variables = get_host_vars(root.path(), "potato", &overrides)?;
+
+    assert_eq!(variables["user"], Value::from("file"));
+    assert_eq!(variables["machine"]["threads"], Value::from(64));
+    assert_eq!(variables["machine"]["arch"], Value::from("x86_64"));
+    assert_eq!(variables["host"], Value::from("other"));
+
+    let sources = variable_sources(&host_variable_layers(root.path(), "potato", &overrides)?);
+    assert_eq!(sources["machine.threads"], "--var machine.threads");
+
+    Ok(())
+}
+
+/// Group variables take precedence over global variables, but not over the host's variables.
+#[test]
+fn group_precedence() -> TestResult {
+    let root = bois_dir()?;
+    let root_path = root.path().to_string_lossy();
+    let group_dir = root.path().join("groups/desktop");
+    fs::create_dir_all(&group_dir)?;
+    fs::write(
+        group_dir.join("vars.yml"),
+        "dns: 8.8.8.8\nmachine:\n  threads: 2\nbrowser: firefox\n",
+    )?;
+
+    let host_layers = host_variable_layers(root.path(), "potato", &[])?;
+    let layers = group_variable_layers(root.path(), "desktop", &host_layers)?;
+    let layer_sources: Vec<_> = layers
+        .iter()
+        .take(5)
+        .map(|layer| layer.source.clone())
+        .collect();
+    assert_eq!(
+        layer_sources,
+        vec![
+            format!("{root_path}/vars.yml"),
+            format!("{root_path}/vars.d/10-dns.yaml"),
+            format!("{root_path}/vars.d/20-user.yml"),
+            format!("{root_path}/groups/desktop/vars.yml"),
+            format!("{root_path}/hosts/potato/vars.yml"),
+        ]
+    );
+    let scopes: Vec<_> = layers.iter().map(|layer| layer.scope).collect();
+    assert_eq!(
+        scopes,
+        vec![
+            VariableScope::Global,
+            VariableScope::Global,
+            VariableScope::Global,
+            VariableScope::Group,
+            VariableScope::Host,
+            VariableScope::Host,
+            VariableScope::Builtin,
+            VariableScope::Builtin,
+        ]
+    );
+
+    let variables = group_variables(
+        root.path(),
+        "desktop",
+        &host_layers,
+        &["desktop".to_string()],
+    )?;
+    assert_eq!(variables["dns"], Value::from("8.8.8.8"));
+    assert_eq!(variables["browser"], Value::from("firefox"));
+    assert_eq!(variables["machine"]["threads"], Value::from(16));
+    assert_eq!(variables["boi_groups"][0], Value::from("desktop"));
+
+    Ok(())
+}
+
+/// Overrides take precedence over group variables and every variable knows its group source.
+#[test]
+fn group_overrides() -> TestResult {
+    let root = bois_dir()?;
+    let root_path = root.path().to_string_lossy();
+    let group_dir = root.path().join("groups/desktop");
+    fs::create_dir_all(group_dir.join("vars.d"))?;
+    fs::write(
+        group_dir.join("vars.yml"),
+        "browser: firefox
+term: foot
+",
+    )?;
+    fs::write(
+        group_dir.join("vars.d/10-term.yml"),
+        "term: kitty
+",
+    )?;
+
+    let overrides = override_layers(&["browser=chromium".to_string()], &[])?;
+    let host_layers = host_variable_layers(root.path(), "potato", &overrides)?;
+    let layers = group_variable_layers(root.path(), "desktop", &host_layers)?;
+    let variables = merge_layers(&layers);
+    assert_eq!(variables["browser"], Value::from("chromium"));
+    assert_eq!(variables["term"], Value::from("kitty"));
+
+    let sources = variable_sources(&layers);
+    assert_eq!(sources["browser"], "--var browser");
+    assert_eq!(
+        sources["term"],
+        format!("{root_path}/groups/desktop/vars.d/10-term.yml")
+    );
+
+    // Other groups don't see the variables of this group.
+    let layers = group_variable_layers(root.path(), "laptop", &host_layers)?;
+    assert!(merge_layers(&layers).get("term").is_none());
+
+    Ok(())
+}
+
+/// Group files are templated with their group's variables, while the host's files and other
+/// groups don't see them. The vars files themselves aren't deployed.
+#[test]
+fn group_variables_deploy() -> TestResult {
+    let root = tempfile::tempdir()?;
+    let template = "# bois_config\n# template: true\n# bois_config\n";
+    write_files(
+        root.path(),
+        &[
+            ("bois/vars.yml", "browser: firefox\n"),
+            ("bois/hosts/potato/host.yml", "groups: [desktop, base]\n"),
+            (
+                "bois/hosts/potato/host.conf",
+                &format!("{template}{{{{ browser }}}} {{{{ term is defined }}}}"),
+            ),
+            ("bois/groups/desktop/vars.yml", "browser: chromium\n"),
+            ("bois/groups/desktop/vars.d/term.yml", "term: foot\n"),
+            (
+                "bois/groups/desktop/desktop.conf",
+                &format!("{template}{{{{ browser }}}} {{{{ term }}}}"),
+            ),
+            (
+                "bois/groups/base/base.conf",
+                &format!("{template}{{{{ browser }}}} {{{{ term is defined }}}}"),
+            ),
+        ],
+    )?;
+    let config = deploy_config(root.path());
+
+    let changeset = plan(&config)?;
+    let mut contents: Vec<_> = changeset
+        .path_operations
+        .iter()
+        .filter_map(|op| match op {
+            PathOperation::File(FileOperation::Create { path, content, .. }) => Some((
+                path.strip_prefix(root.path().join("target"))
+                    .ok()?
+                    .to_path_buf(),
+                String::from_utf8_lossy(content).to_string(),
+            )),
+            _ => None,
+        })
+        .collect();
+    contents.sort();
+    assert_eq!(
+        contents,
+        vec![
+            ("base.conf".into(), "firefox false\n".to_string()),
+            ("desktop.conf".into(), "chromium foot\n".to_string()),
+            ("host.conf".into(), "firefox false\n".to_string()),
+        ]
+    );
+
+    Ok(())
+}
+
+/// Every leaf is annotated with its source, while mappings are kept.
+#[test]
+fn annotated_sources() -> TestResult {
+    let root = bois_dir()?;
+    let root_path = root.path().to_string_lossy();
+    let overrides = override_layers(&["user=cli".to_string()], &[])?;
+
+    let layers = host_variable_layers(root.path(), "potato", &overrides)?;
+    let variables = merge_layers(&layers);
+    let annotated = annotate_sources(&variables, &variable_sources(&layers));
+
+    let expected: Value = serde_yaml::from_str(&format!(
+        "arch:\n  value: x86_64\n  source: {root_path}/vars.yml\nthreads:\n  value: 16\n  source: \
+         {root_path}/hosts/potato/vars.yml\n"
+    ))?;
+    assert_eq!(annotated["machine"], expected);
+    assert_eq!(annotated["user"]["source"], Value::from("--var user"));
+    assert_eq!(annotated["host"]["source"], Value::from(BUILTIN_SOURCE));
+    assert_eq!(
+        annotated["facts"]["has_battery"]["source"],
+        Value::from(FACTS_SOURCE)
+    );
+
+    Ok(())
+}